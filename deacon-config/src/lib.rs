@@ -1,17 +1,175 @@
+//! The configuration of Deacon.
+//!
+//! The configuration is read from `deacon/config.toml` in the user's config directory
+//! (`$XDG_CONFIG_HOME`, usually `~/.config`, on Linux). Every field is optional:
+//! ```toml
+//! upper_prompt = "{bold}{green}{user}@{host}{reset}: {bold}{blue}{cwd}{reset}"
+//! lower_prompt = "{red}{status}{reset}$ "
+//! use_deacon_dir = true
+//!
+//! [integrations]
+//! rust_integration = true
+//! git_integration = true
+//!
+//! [command_aliases]
+//! ll = "ls -la"
+//! ```
+
 use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
 
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize)]
+#[serde(default)]
 pub struct DeaconConfig {
-	/// The contents of the upper line. Supports ANSI.
+	/// The template of the line above the prompt. Supports placeholders and colour directives,
+	/// such as `{bold}{green}{user}@{host}{reset}`.
 	pub upper_prompt: String,
-	/// The contents of the Readline prompt. Does not support ANSI.
+	/// The template of the Readline prompt. Supports the same placeholders and colour directives
+	/// as the upper prompt.
 	pub lower_prompt: String,
 	/// Integration configuration.
 	pub integrations: Integrations,
 	/// Whether to use Deacon's internal `dir` implementation.
+	/// If disabled, `ls` runs the `ls` executable instead of the builtin.
 	pub use_deacon_dir: bool,
 	pub command_aliases: HashMap<String, String>
 }
 
+impl Default for DeaconConfig {
+	fn default() -> Self {
+		DeaconConfig {
+			upper_prompt: "{bold}{green}{user}@{host}{reset}: {bold}{blue}{cwd}{reset} {rgb:255,165,0}{rust} {git}{reset} {bold}{red}{status}{reset}".to_string(),
+			lower_prompt: "$ ".to_string(),
+			integrations: Integrations::default(),
+			use_deacon_dir: true,
+			command_aliases: HashMap::new()
+		}
+	}
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize)]
+#[serde(default)]
 pub struct Integrations {
-	pub rust_integration: bool
-}
\ No newline at end of file
+	pub rust_integration: bool,
+	pub git_integration: bool
+}
+
+impl Default for Integrations {
+	fn default() -> Self {
+		Integrations {
+			rust_integration: true,
+			git_integration: true
+		}
+	}
+}
+
+impl DeaconConfig {
+	/// Returns the path of the configuration file, if the config directory is known.
+	pub fn path() -> Option<PathBuf> {
+		dirs::config_dir().map(|f| f.join("deacon").join("config.toml"))
+	}
+
+	/// Loads the configuration from the [default path](DeaconConfig::path).
+	/// If there is no configuration file, the default configuration is returned.
+	pub fn load() -> Result<DeaconConfig, ConfigError> {
+		match DeaconConfig::path() {
+			Some(path) => DeaconConfig::load_from(path),
+			None => Ok(DeaconConfig::default())
+		}
+	}
+
+	/// Loads the configuration from the given file.
+	/// If the file does not exist, the default configuration is returned.
+	///
+	/// ```
+	/// # use deacon_config::DeaconConfig;
+	/// let config = DeaconConfig::load_from("/this/file/does/not/exist.toml").unwrap();
+	/// assert_eq!(config, DeaconConfig::default());
+	/// ```
+	pub fn load_from(path: impl AsRef<Path>) -> Result<DeaconConfig, ConfigError> {
+		match fs::read_to_string(path) {
+			Ok(contents) => DeaconConfig::parse(&contents),
+			Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(DeaconConfig::default()),
+			Err(err) => Err(ConfigError::Io(err))
+		}
+	}
+
+	/// Parses the configuration from TOML. Missing fields take their default values.
+	///
+	/// ```
+	/// # use deacon_config::DeaconConfig;
+	/// let config = DeaconConfig::parse("lower_prompt = '> '\n[integrations]\ngit_integration = false").unwrap();
+	/// assert_eq!(config.lower_prompt, "> ");
+	/// assert!(config.integrations.rust_integration);
+	/// assert!(!config.integrations.git_integration);
+	/// assert!(DeaconConfig::parse("use_deacon_dir = 'yes'").is_err());
+	/// ```
+	pub fn parse(contents: &str) -> Result<DeaconConfig, ConfigError> {
+		toml_edit::de::from_str(contents).map_err(ConfigError::Parse)
+	}
+
+	/// Sets the given alias in the `[command_aliases]` table of the given file, or removes it if there is no value,
+	/// creating the file and its directory if needed. The rest of the file is kept as it is, comments included.
+	///
+	/// ```
+	/// # use deacon_config::DeaconConfig;
+	/// let path = std::env::temp_dir().join("deacon-save-alias.toml");
+	/// std::fs::write(&path, "# my prompt\nlower_prompt = '> '\n").unwrap();
+	/// DeaconConfig::save_alias(&path, "ll", Some("ls -la")).unwrap();
+	/// DeaconConfig::save_alias(&path, "la", Some("ls -a")).unwrap();
+	/// DeaconConfig::save_alias(&path, "la", None).unwrap();
+	/// let contents = std::fs::read_to_string(&path).unwrap();
+	/// assert_eq!(contents, "# my prompt\nlower_prompt = '> '\n\n[command_aliases]\nll = \"ls -la\"\n");
+	/// # std::fs::remove_file(path).unwrap();
+	/// ```
+	pub fn save_alias(path: impl AsRef<Path>, name: &str, value: Option<&str>) -> Result<(), ConfigError> {
+		let path = path.as_ref();
+		let contents = match fs::read_to_string(path) {
+			Ok(contents) => contents,
+			Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+			Err(err) => return Err(ConfigError::Io(err))
+		};
+		let mut document = contents.parse::<toml_edit::DocumentMut>().map_err(|f| ConfigError::Edit(f.to_string()))?;
+		let aliases = document.entry("command_aliases")
+			.or_insert(toml_edit::table())
+			.as_table_like_mut()
+			.ok_or_else(|| ConfigError::Edit("`command_aliases` is not a table".to_string()))?;
+		match value {
+			Some(value) => {
+				aliases.insert(name, toml_edit::value(value));
+			}
+			None => {
+				aliases.remove(name);
+			}
+		}
+		if let Some(parent) = path.parent() {
+			fs::create_dir_all(parent).map_err(ConfigError::Io)?;
+		}
+		fs::write(path, document.to_string()).map_err(ConfigError::Io)
+	}
+}
+
+/// An error which occurred while loading the configuration.
+#[derive(Debug)]
+pub enum ConfigError {
+	Io(io::Error),
+	Parse(toml_edit::de::Error),
+	/// The file could not be edited in place, such as when it is not valid TOML.
+	Edit(String)
+}
+
+impl fmt::Display for ConfigError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ConfigError::Io(err) => write!(f, "{}", err),
+			ConfigError::Parse(err) => write!(f, "{}", err),
+			ConfigError::Edit(err) => write!(f, "{}", err)
+		}
+	}
+}
+
+impl std::error::Error for ConfigError {}
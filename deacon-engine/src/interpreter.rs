@@ -0,0 +1,835 @@
+//! The tree-walking interpreter for Deacon scripts.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use deacon_parse::ast::{Block, Command, Condition, Expr, For, If, Items, Pipeline, Redirect, Script, Statement, While};
+use deacon_parse::function::{Call, Function};
+use deacon_parse::lexer::{quote, substitutions, Quote};
+use deacon_parse::parse_script;
+use deacon_parse::types::DeaconType;
+pub use crate::scope::Scope;
+
+/// The maximum depth of nested function calls, so that infinite recursion
+/// fails with an error instead of overflowing the stack of the shell.
+const MAX_CALL_DEPTH: usize = 256;
+
+/// The exit code of a script stopped by the user, as in other shells: 128 plus the number of SIGINT.
+const INTERRUPTED: i32 = 130;
+
+/// Runs what the interpreter cannot run by itself, namely builtins and external processes.
+/// The shell implements this trait.
+pub trait Host {
+    /// Executes a command, a pipeline or a command list of builtins and processes, returning its exit code.
+    /// Variables of the given scope are substituted into the arguments.
+    fn execute(&mut self, expr: &Expr, scope: &Scope) -> i32;
+
+    /// Executes an expression like [`execute`](Host::execute), but returns its standard output along with
+    /// its exit code instead of printing it. By default, the output is not captured.
+    fn capture(&mut self, expr: &Expr, scope: &Scope) -> (i32, String) {
+        (self.execute(expr, scope), String::new())
+    }
+
+    /// Executes the commands of a pipeline like [`execute`](Host::execute), with the given input as the standard
+    /// input of the first command, as if it were the output of a previous stage. If `capture` is set, the standard
+    /// output is returned as with [`capture`](Host::capture). By default, the input is ignored.
+    fn execute_piped(&mut self, commands: &[Command], scope: &Scope, _input: String, capture: bool) -> (i32, String) {
+        let expr = Expr::Pipeline(Pipeline { commands: commands.to_vec() });
+        if capture {
+            self.capture(&expr, scope)
+        } else {
+            (self.execute(&expr, scope), String::new())
+        }
+    }
+
+    /// Expands a word of a call into the argument it represents, as [`execute`](Host::execute) would.
+    fn expand(&self, word: &str, scope: &Scope) -> String;
+
+    /// Replaces the name of a call, given with its arguments, if it is an alias. This happens before
+    /// the call is run as a function, `not` or by the host. By default, there are no aliases.
+    fn expand_aliases(&self, words: Vec<String>) -> Vec<String> {
+        words
+    }
+
+    /// Expands a word of a `for` loop into its items. By default, the word is [expanded](Host::expand)
+    /// into a single item.
+    fn expand_items(&self, word: &str, scope: &Scope) -> Vec<String> {
+        vec![self.expand(word, scope)]
+    }
+
+    /// Returns whether the user asked to stop the script, such as with Ctrl-C, since this was last called.
+    /// Loops check this after every iteration.
+    fn is_interrupted(&mut self) -> bool {
+        false
+    }
+
+    /// Takes the functions that the last [execution](Host::execute) made available,
+    /// such as the exported functions of a script run with `using`.
+    fn take_imports(&mut self) -> Vec<Function> {
+        Vec::new()
+    }
+
+    /// Takes the exit that the last [execution](Host::execute) requested, such as with an `exit` builtin.
+    /// `Some(None)` means to exit with the exit code of the previous statement.
+    fn take_exit(&mut self) -> Option<Option<i32>> {
+        None
+    }
+}
+
+/// Runs [scripts](Script) statement by statement. Calls to functions declared in a script are run by the
+/// interpreter itself, with their [formal arguments](deacon_parse::function::FormalArg) bound to the
+/// arguments of the call, and so is `not`, which negates the exit code of the call after it.
+/// Everything else is run by the [host](Host).
+///
+/// Command substitutions `$(...)` are run by the interpreter too, before the words they are in are
+/// [expanded](Host::expand): the standard output of the script inside, without its trailing newlines,
+/// takes the place of the substitution as a single argument.
+///
+/// ```
+/// # use deacon_engine::interpreter::*;
+/// # use deacon_parse::ast::Expr;
+/// struct Echo(Vec<String>);
+///
+/// impl Host for Echo {
+///     fn execute(&mut self, expr: &Expr, scope: &Scope) -> i32 {
+///         if let Expr::Command(command) = expr {
+///             self.0.push(command.call.args.iter().map(|f| self.expand(f, scope)).collect::<Vec<_>>().join(" "));
+///         }
+///         0
+///     }
+///
+///     fn expand(&self, word: &str, scope: &Scope) -> String {
+///         scope.substitute(word.trim_matches('"'))
+///     }
+/// }
+///
+/// let script = deacon_parse::parse_script("func greet(name: string) {\n    echo hello $name\n}\ngreet \"the world\"").unwrap();
+/// let mut interpreter = Interpreter::new(Echo(vec![]));
+/// assert_eq!(interpreter.run(&script), 0);
+/// assert_eq!(interpreter.host.0, vec!["hello the world"]);
+/// ```
+pub struct Interpreter<H: Host> {
+    pub host: H,
+    functions: HashMap<String, Function>,
+    scope: Scope,
+    depth: usize,
+    /// The number of loops around the current statement, in the current function.
+    loops: usize,
+    /// A `break` or `continue` which skips the rest of the innermost loop's body.
+    jump: Option<Statement>,
+    /// Whether a loop was interrupted, after which nothing else in the script runs.
+    interrupted: bool,
+    /// The output captured by the innermost `$(...)` or `for` loop over `$(...)`, if any.
+    output: Option<String>,
+    /// The exit code requested by the host, after which nothing else runs.
+    exit: Option<i32>
+}
+
+impl<H: Host> Interpreter<H> {
+    /// Creates an interpreter without any functions or variables.
+    pub fn new(host: H) -> Interpreter<H> {
+        Interpreter {
+            host,
+            functions: HashMap::new(),
+            scope: Scope::new(),
+            depth: 0,
+            loops: 0,
+            jump: None,
+            interrupted: false,
+            output: None,
+            exit: None
+        }
+    }
+
+    /// Returns the exit code requested with the host's `exit`, if any. Once an exit is requested,
+    /// the rest of the script is skipped.
+    ///
+    /// ```
+    /// # use deacon_engine::interpreter::*;
+    /// # use deacon_parse::ast::Expr;
+    /// struct Exit(Vec<String>);
+    ///
+    /// impl Host for Exit {
+    ///     fn execute(&mut self, expr: &Expr, _: &Scope) -> i32 {
+    ///         let Expr::Command(command) = expr else { return 0 };
+    ///         self.0.push(command.call.name.clone());
+    ///         if command.call.name == "false" { 1 } else { 0 }
+    ///     }
+    ///
+    ///     fn expand(&self, word: &str, _: &Scope) -> String {
+    ///         word.to_string()
+    ///     }
+    ///
+    ///     fn take_exit(&mut self) -> Option<Option<i32>> {
+    ///         self.0.last().filter(|f| *f == "exit").map(|_| None)
+    ///     }
+    /// }
+    ///
+    /// let script = deacon_parse::parse_script("false\nexit\necho unreachable").unwrap();
+    /// let mut interpreter = Interpreter::new(Exit(vec![]));
+    /// assert_eq!(interpreter.run(&script), 1);
+    /// assert_eq!(interpreter.exit_code(), Some(1));
+    /// assert_eq!(interpreter.host.0, vec!["false", "exit"]);
+    /// ```
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit
+    }
+
+    /// Returns the exit code of the last statement, that is, `$?`.
+    pub fn status(&self) -> i32 {
+        self.scope.status
+    }
+
+    /// Returns the function of the given name, if it has been declared.
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name)
+    }
+
+    /// Returns every declared function.
+    pub fn functions(&self) -> impl Iterator<Item = &Function> {
+        self.functions.values()
+    }
+
+    /// Returns every function declared with `export`.
+    pub fn exported_functions(&self) -> impl Iterator<Item = &Function> {
+        self.functions.values().filter(|f| f.exported)
+    }
+
+    /// Runs every statement of the script, returning the exit code of the last one,
+    /// or the requested exit code if the script exits early.
+    ///
+    /// ```
+    /// # use deacon_engine::interpreter::*;
+    /// # use deacon_parse::ast::Expr;
+    /// struct Calls(Vec<String>);
+    ///
+    /// impl Host for Calls {
+    ///     fn execute(&mut self, expr: &Expr, _: &Scope) -> i32 {
+    ///         let Expr::Command(command) = expr else { return 0 };
+    ///         self.0.push(command.call.name.clone());
+    ///         if command.call.name == "false" { 1 } else { 0 }
+    ///     }
+    ///
+    ///     fn expand(&self, word: &str, scope: &Scope) -> String {
+    ///         scope.substitute(word)
+    ///     }
+    /// }
+    ///
+    /// let source = "let $x = a\nif not false {\n    first\n}\nif $x != a {\n    second\n} else if true {\n    third\n}";
+    /// let mut interpreter = Interpreter::new(Calls(vec![]));
+    /// assert_eq!(interpreter.run(&deacon_parse::parse_script(source).unwrap()), 0);
+    /// assert_eq!(interpreter.host.0, vec!["false", "first", "third"]);
+    /// ```
+    ///
+    /// Loops run until their condition fails or they run out of items, `break` stops the innermost loop,
+    /// and `continue` skips to its next iteration:
+    /// ```
+    /// # use deacon_engine::interpreter::*;
+    /// # use deacon_parse::ast::Expr;
+    /// struct Echo(Vec<String>);
+    ///
+    /// impl Host for Echo {
+    ///     fn execute(&mut self, expr: &Expr, scope: &Scope) -> i32 {
+    ///         let Expr::Command(command) = expr else { return 0 };
+    ///         self.0.push(command.call.args.iter().map(|f| self.expand(f, scope)).collect::<Vec<_>>().join(" "));
+    ///         0
+    ///     }
+    ///
+    ///     fn capture(&mut self, _: &Expr, _: &Scope) -> (i32, String) {
+    ///         (0, "a\nb\nc\n".to_string())
+    ///     }
+    ///
+    ///     fn expand(&self, word: &str, scope: &Scope) -> String {
+    ///         scope.substitute(word)
+    ///     }
+    /// }
+    ///
+    /// let source = "for $x in $(letters) {\n    if $x == b { continue }\n    for $y in 1 2 3 {\n        if $y == 3 { break }\n        echo $x$y\n    }\n}";
+    /// let mut interpreter = Interpreter::new(Echo(vec![]));
+    /// interpreter.run(&deacon_parse::parse_script(source).unwrap());
+    /// assert_eq!(interpreter.host.0, vec!["a1", "a2", "c1", "c2"]);
+    /// ```
+    ///
+    /// The output of a command substitution is a single argument, even if it has spaces or quotes:
+    /// ```
+    /// # use deacon_engine::interpreter::*;
+    /// # use deacon_parse::ast::Expr;
+    /// struct Echo(Vec<String>);
+    ///
+    /// impl Host for Echo {
+    ///     fn execute(&mut self, expr: &Expr, scope: &Scope) -> i32 {
+    ///         let Expr::Command(command) = expr else { return 0 };
+    ///         self.0.push(command.call.args.iter().map(|f| format!("[{}]", self.expand(f, scope))).collect());
+    ///         0
+    ///     }
+    ///
+    ///     fn capture(&mut self, expr: &Expr, scope: &Scope) -> (i32, String) {
+    ///         let Expr::Command(command) = expr else { return (0, String::new()) };
+    ///         (0, command.call.args.iter().map(|f| self.expand(f, scope) + "\n").collect())
+    ///     }
+    ///
+    ///     fn expand(&self, word: &str, scope: &Scope) -> String {
+    ///         deacon_parse::lexer::unquote(&scope.substitute(word))
+    ///     }
+    /// }
+    ///
+    /// let source = "let $branch = $(echo main)\necho \"on $branch\" $(echo \"it's $(echo a b)\")";
+    /// let mut interpreter = Interpreter::new(Echo(vec![]));
+    /// interpreter.run(&deacon_parse::parse_script(source).unwrap());
+    /// assert_eq!(interpreter.host.0, vec!["[on main][it's a\nb]"]);
+    /// ```
+    ///
+    /// `let` declares a variable in the innermost block, function call or loop iteration, shadowing any variable
+    /// of the same name around it, and a reassignment changes the closest variable of that name. A function sees
+    /// the variables declared at the top level of the script, but not those of its caller:
+    /// ```
+    /// # use deacon_engine::interpreter::*;
+    /// # use deacon_parse::ast::Expr;
+    /// struct Echo(Vec<String>);
+    ///
+    /// impl Host for Echo {
+    ///     fn execute(&mut self, expr: &Expr, scope: &Scope) -> i32 {
+    ///         let Expr::Command(command) = expr else { return 0 };
+    ///         self.0.push(command.call.args.iter().map(|f| self.expand(f, scope)).collect::<Vec<_>>().join(" "));
+    ///         0
+    ///     }
+    ///
+    ///     fn expand(&self, word: &str, scope: &Scope) -> String {
+    ///         deacon_parse::lexer::unquote(&scope.substitute(word))
+    ///     }
+    /// }
+    ///
+    /// let source = "let $x = a\n{\n    let $x = b\n    $x = c\n    echo \"inner $x\"\n}\necho $x\n{ $x = d }\necho $x";
+    /// let mut interpreter = Interpreter::new(Echo(vec![]));
+    /// interpreter.run(&deacon_parse::parse_script(source).unwrap());
+    /// assert_eq!(interpreter.host.0, vec!["inner c", "a", "d"]);
+    /// assert_eq!(interpreter.run(&deacon_parse::parse_script("$y = 1").unwrap()), 1);
+    ///
+    /// let source = "func show() {\n    echo $x $local\n    $x = e\n}\n{\n    let $local = f\n    show\n}\necho $x";
+    /// interpreter.host.0.clear();
+    /// interpreter.run(&deacon_parse::parse_script(source).unwrap());
+    /// assert_eq!(interpreter.host.0, vec!["d $local", "e"]);
+    /// ```
+    ///
+    /// A function can be the first command of a pipeline, and its output can be redirected to a file:
+    /// ```
+    /// # use deacon_engine::interpreter::*;
+    /// # use deacon_parse::ast::{Command, Expr};
+    /// struct Upper(Vec<String>);
+    ///
+    /// impl Host for Upper {
+    ///     fn execute(&mut self, _: &Expr, _: &Scope) -> i32 {
+    ///         0
+    ///     }
+    ///
+    ///     fn capture(&mut self, expr: &Expr, scope: &Scope) -> (i32, String) {
+    ///         let Expr::Command(command) = expr else { return (0, String::new()) };
+    ///         (0, command.call.args.iter().map(|f| self.expand(f, scope)).collect::<Vec<_>>().join(" ") + "\n")
+    ///     }
+    ///
+    ///     fn execute_piped(&mut self, commands: &[Command], _: &Scope, input: String, _: bool) -> (i32, String) {
+    ///         self.0.push(format!("{} {}", commands[0].call.name, input.to_uppercase()));
+    ///         (0, String::new())
+    ///     }
+    ///
+    ///     fn expand(&self, word: &str, scope: &Scope) -> String {
+    ///         scope.substitute(word)
+    ///     }
+    /// }
+    ///
+    /// let path = std::env::temp_dir().join("deacon-function-output.txt");
+    /// let source = format!("func greet(name: string) {{\n    echo hi $name\n}}\ngreet you | upper\ngreet file > {}", path.display());
+    /// let mut interpreter = Interpreter::new(Upper(vec![]));
+    /// assert_eq!(interpreter.run(&deacon_parse::parse_script(source.as_str()).unwrap()), 0);
+    /// assert_eq!(interpreter.host.0, vec!["upper HI YOU\n"]);
+    /// assert_eq!(std::fs::read_to_string(&path).unwrap(), "hi file\n");
+    /// # std::fs::remove_file(path).unwrap();
+    /// ```
+    ///
+    /// Aliases are expanded before anything else, so an alias may stand for a function or be negated with `not`:
+    /// ```
+    /// # use deacon_engine::interpreter::*;
+    /// # use deacon_parse::ast::Expr;
+    /// struct Aliases(Vec<String>);
+    ///
+    /// impl Host for Aliases {
+    ///     fn execute(&mut self, expr: &Expr, scope: &Scope) -> i32 {
+    ///         let Expr::Command(command) = expr else { return 0 };
+    ///         self.0.push(std::iter::once(&command.call.name).chain(&command.call.args).map(|f| self.expand(f, scope)).collect::<Vec<_>>().join(" "));
+    ///         1
+    ///     }
+    ///
+    ///     fn expand(&self, word: &str, scope: &Scope) -> String {
+    ///         scope.substitute(word)
+    ///     }
+    ///
+    ///     fn expand_aliases(&self, mut words: Vec<String>) -> Vec<String> {
+    ///         match words[0].as_str() {
+    ///             "hi" => drop(words.splice(..1, ["greet".to_string(), "you".to_string()])),
+    ///             "fails" => words[0] = "false".to_string(),
+    ///             _ => {}
+    ///         }
+    ///         words
+    ///     }
+    /// }
+    ///
+    /// let source = "func greet(name: string) {\n    echo hi $name\n}\nhi\nnot fails";
+    /// let mut interpreter = Interpreter::new(Aliases(vec![]));
+    /// assert_eq!(interpreter.run(&deacon_parse::parse_script(source).unwrap()), 0);
+    /// assert_eq!(interpreter.host.0, vec!["echo hi you", "false"]);
+    /// ```
+    pub fn run(&mut self, script: &Script) -> i32 {
+        // an earlier interrupt was meant for something else.
+        self.host.is_interrupted();
+        self.interrupted = false;
+        self.run_statements(&script.statements)
+    }
+
+    /// Returns the exit code to stop with, if the script exited or was interrupted.
+    fn stopped(&self) -> Option<i32> {
+        self.exit.or(self.interrupted.then_some(INTERRUPTED))
+    }
+
+    fn run_statements(&mut self, statements: &[Statement]) -> i32 {
+        for statement in statements {
+            if let Some(code) = self.stopped() {
+                return code;
+            }
+            if self.jump.is_some() {
+                break
+            }
+            self.scope.status = self.run_statement(statement);
+        }
+        self.stopped().unwrap_or(self.scope.status)
+    }
+
+    fn run_statement(&mut self, statement: &Statement) -> i32 {
+        match statement {
+            Statement::Expr(expr) => self.eval(expr),
+            Statement::VariableDecl(variable) => {
+                let value = self.expand(&variable.value);
+                self.scope.set(&variable.identifier, value);
+                0
+            }
+            Statement::VariableReassignment(variable) => {
+                let value = self.expand(&variable.value);
+                if self.scope.assign(&variable.identifier, value) {
+                    0
+                } else {
+                    eprintln!("${}: not declared; declare it with `let ${} = ...` first", variable.identifier, variable.identifier);
+                    1
+                }
+            }
+            Statement::FunctionDecl(function) => {
+                self.functions.insert(function.name.clone(), function.clone());
+                0
+            }
+            Statement::Block(block) => self.run_block(block),
+            Statement::If(r#if) => self.run_if(r#if),
+            Statement::While(r#while) => self.run_while(r#while),
+            Statement::For(r#for) => self.run_for(r#for),
+            Statement::Break | Statement::Continue if self.loops == 0 => {
+                let keyword = if *statement == Statement::Break { "break" } else { "continue" };
+                eprintln!("{}: only meaningful in a `while` or `for` loop", keyword);
+                1
+            }
+            Statement::Break | Statement::Continue => {
+                self.jump = Some(statement.clone());
+                0
+            }
+        }
+    }
+
+    /// Runs the body of the loop as long as its condition holds, returning the exit code of the last
+    /// iteration, or 0 if there was none.
+    fn run_while(&mut self, r#while: &While) -> i32 {
+        let mut code = 0;
+        self.loops += 1;
+        while self.stopped().is_none() && self.test(&r#while.condition) {
+            code = self.run_block(&r#while.body);
+            if self.end_iteration() {
+                break
+            }
+        }
+        self.loops -= 1;
+        code
+    }
+
+    /// Runs the body of the loop once for every item, returning the exit code of the last iteration,
+    /// or 0 if there was none.
+    fn run_for(&mut self, r#for: &For) -> i32 {
+        let items = match &r#for.items {
+            Items::Words(words) => {
+                let mut items = vec![];
+                for word in words {
+                    let word = self.substitute(word);
+                    items.extend(self.host.expand_items(&word, &self.scope));
+                }
+                items
+            }
+            Items::Output(expr) => self.capture(|f| f.eval(expr)).1.lines().map(str::to_string).collect()
+        };
+        let mut code = 0;
+        self.loops += 1;
+        for item in items {
+            if self.stopped().is_some() {
+                break
+            }
+            self.scope.push();
+            self.scope.set(&r#for.variable, item);
+            code = self.run_statements(&r#for.body.statements);
+            self.scope.pop();
+            if self.end_iteration() {
+                break
+            }
+        }
+        self.loops -= 1;
+        code
+    }
+
+    /// Ends an iteration of a loop, returning whether the loop should stop.
+    fn end_iteration(&mut self) -> bool {
+        if self.host.is_interrupted() {
+            self.interrupted = true;
+        }
+        match self.jump.take() {
+            Some(Statement::Break) => true,
+            _ => self.stopped().is_some()
+        }
+    }
+
+    /// Runs something with the interpreter, returning its exit code along with its standard output.
+    fn capture(&mut self, run: impl FnOnce(&mut Self) -> i32) -> (i32, String) {
+        let outer = self.output.replace(String::new());
+        let code = run(self);
+        let output = std::mem::replace(&mut self.output, outer).unwrap_or_default();
+        (code, output)
+    }
+
+    /// Replaces every command substitution `$(...)` of the word with the output of the script inside it,
+    /// quoted so that the output is a single argument and is not expanded any further.
+    fn substitute<'w>(&mut self, word: &'w str) -> Cow<'w, str> {
+        let found = substitutions(word);
+        if found.is_empty() {
+            return Cow::Borrowed(word);
+        }
+        let mut output = String::new();
+        let mut last = 0;
+        for (range, quoting) in found {
+            output.push_str(&word[last..range.start]);
+            let source = &word[range.start + "$(".len()..range.end - 1];
+            let captured = match parse_script(source) {
+                Ok(script) => self.capture(|f| f.run_block(&Block { statements: script.statements })).1,
+                Err(err) => {
+                    eprintln!("$({}): {}", source, err.message);
+                    String::new()
+                }
+            };
+            let text = quote(captured.trim_end_matches(['\n', '\r']));
+            match quoting {
+                // end the double quotes around the substitution, so that the output is not expanded.
+                Quote::Double => output.push_str(&format!("\"{}\"", text)),
+                _ => output.push_str(&text)
+            }
+            last = range.end;
+        }
+        output.push_str(&word[last..]);
+        Cow::Owned(output)
+    }
+
+    /// Replaces the command substitutions of every word of the expression, as in [`substitute`](Self::substitute).
+    fn substitute_expr<'e>(&mut self, expr: &'e Expr) -> Cow<'e, Expr> {
+        let has_substitutions = |commands: &[Command]| commands.iter()
+            .any(|f| f.call.args.iter().chain([&f.call.name]).chain(f.redirects.iter().filter_map(redirect_path)).any(|f| f.contains("$(")));
+        match expr {
+            Expr::Command(command) if has_substitutions(std::slice::from_ref(command)) => {
+                Cow::Owned(Expr::Command(self.substitute_command(command)))
+            }
+            Expr::Pipeline(pipeline) if has_substitutions(&pipeline.commands) => {
+                Cow::Owned(Expr::Pipeline(Pipeline { commands: pipeline.commands.iter().map(|f| self.substitute_command(f)).collect() }))
+            }
+            Expr::Background(pipeline) if has_substitutions(&pipeline.commands) => {
+                Cow::Owned(Expr::Background(Pipeline { commands: pipeline.commands.iter().map(|f| self.substitute_command(f)).collect() }))
+            }
+            _ => Cow::Borrowed(expr)
+        }
+    }
+
+    /// Replaces the [aliases](Host::expand_aliases) of the names of the calls of the expression.
+    fn expand_aliases<'e>(&self, expr: &'e Expr) -> Cow<'e, Expr> {
+        let expand = |command: &Command| {
+            let words = std::iter::once(&command.call.name).chain(&command.call.args).cloned().collect::<Vec<String>>();
+            match self.host.expand_aliases(words.clone()) {
+                expanded if expanded == words || expanded.is_empty() => None,
+                mut expanded => Some(Command {
+                    call: Call { name: expanded.remove(0), args: expanded },
+                    redirects: command.redirects.clone()
+                })
+            }
+        };
+        let expand_pipeline = |pipeline: &Pipeline| {
+            let expanded = pipeline.commands.iter().map(expand).collect::<Vec<Option<Command>>>();
+            expanded.iter().any(Option::is_some).then(|| Pipeline {
+                commands: expanded.into_iter().zip(&pipeline.commands).map(|(f, command)| f.unwrap_or_else(|| command.clone())).collect()
+            })
+        };
+        match expr {
+            Expr::Command(command) => expand(command).map_or(Cow::Borrowed(expr), |f| Cow::Owned(Expr::Command(f))),
+            Expr::Pipeline(pipeline) => expand_pipeline(pipeline).map_or(Cow::Borrowed(expr), |f| Cow::Owned(Expr::Pipeline(f))),
+            Expr::Background(pipeline) => expand_pipeline(pipeline).map_or(Cow::Borrowed(expr), |f| Cow::Owned(Expr::Background(f))),
+            Expr::And(_, _) | Expr::Or(_, _) => Cow::Borrowed(expr)
+        }
+    }
+
+    /// Replaces the command substitutions of the name, the arguments and the redirected paths of the command.
+    fn substitute_command(&mut self, command: &Command) -> Command {
+        let redirects = command.redirects.iter()
+            .map(|redirect| match redirect {
+                Redirect::Stdin(path) => Redirect::Stdin(self.substitute(path).into_owned()),
+                Redirect::Stdout { path, append } => Redirect::Stdout { path: self.substitute(path).into_owned(), append: *append },
+                Redirect::Stderr { path, append } => Redirect::Stderr { path: self.substitute(path).into_owned(), append: *append },
+                Redirect::Both { path, append } => Redirect::Both { path: self.substitute(path).into_owned(), append: *append },
+                Redirect::StderrToStdout => Redirect::StderrToStdout
+            })
+            .collect();
+        Command {
+            call: Call {
+                name: self.substitute(&command.call.name).into_owned(),
+                args: command.call.args.iter().map(|f| self.substitute(f).into_owned()).collect()
+            },
+            redirects
+        }
+    }
+
+    /// Expands a word into the argument it represents, running its command substitutions first.
+    fn expand(&mut self, word: &str) -> String {
+        let word = self.substitute(word);
+        self.host.expand(&word, &self.scope)
+    }
+
+    /// Runs the block of the first branch whose condition holds, or the `else` block.
+    /// If no block is run, the exit code is 0.
+    fn run_if(&mut self, r#if: &If) -> i32 {
+        for (condition, block) in &r#if.branches {
+            if self.test(condition) {
+                return self.run_block(block);
+            }
+        }
+        match &r#if.otherwise {
+            Some(block) => self.run_block(block),
+            None => 0
+        }
+    }
+
+    /// Returns whether the condition holds.
+    fn test(&mut self, condition: &Condition) -> bool {
+        match condition {
+            Condition::Bool(value) => *value,
+            Condition::Compare { left, right, equal } => {
+                (self.expand(left) == self.expand(right)) == *equal
+            }
+            Condition::Expr(expr) => self.eval(expr) == 0
+        }
+    }
+
+    fn run_block(&mut self, block: &Block) -> i32 {
+        self.scope.push();
+        let code = self.run_statements(&block.statements);
+        self.scope.pop();
+        code
+    }
+
+    /// Evaluates an expression, returning its exit code.
+    pub fn eval(&mut self, expr: &Expr) -> i32 {
+        if let Some(code) = self.stopped() {
+            return code;
+        }
+        let expr = &*self.expand_aliases(expr);
+        let code = match expr {
+            Expr::Command(command) if self.functions.contains_key(&command.call.name) => self.call(command),
+            Expr::Command(command) if command.call.name == "not" && !command.call.args.is_empty() => {
+                // the negated call may be a function, so it cannot be left to the host.
+                let negated = Command {
+                    call: Call { name: command.call.args[0].clone(), args: command.call.args[1..].to_vec() },
+                    redirects: command.redirects.clone()
+                };
+                match self.eval(&Expr::Command(negated)) {
+                    0 => 1,
+                    _ => 0
+                }
+            }
+            Expr::Pipeline(pipeline) if pipeline.commands.iter().any(|f| self.functions.contains_key(&f.call.name)) => {
+                self.run_pipeline(pipeline)
+            }
+            Expr::Background(pipeline) if pipeline.commands.iter().any(|f| self.functions.contains_key(&f.call.name)) => {
+                let function = pipeline.commands.iter().find(|f| self.functions.contains_key(&f.call.name)).unwrap();
+                eprintln!("{}: functions cannot run in the background", function.call.name);
+                1
+            }
+            Expr::Command(_) | Expr::Pipeline(_) | Expr::Background(_) => {
+                let expr = self.substitute_expr(expr);
+                let code = match &mut self.output {
+                    Some(output) => {
+                        let (code, captured) = self.host.capture(&expr, &self.scope);
+                        output.push_str(&captured);
+                        code
+                    }
+                    None => self.host.execute(&expr, &self.scope)
+                };
+                self.finish_host(code)
+            }
+            Expr::And(left, right) => match self.eval(left) {
+                0 => self.eval(right),
+                code => code
+            },
+            Expr::Or(left, right) => match self.eval(left) {
+                0 => 0,
+                _ => self.eval(right)
+            }
+        };
+        self.scope.status = code;
+        code
+    }
+
+    /// Takes what the host left for the interpreter after running something, namely the imported functions
+    /// and the requested exit, returning the exit code of what it ran or the exit code to stop with.
+    fn finish_host(&mut self, code: i32) -> i32 {
+        for function in self.host.take_imports() {
+            self.functions.insert(function.name.clone(), function);
+        }
+        match self.host.take_exit() {
+            Some(exit) => {
+                let exit = exit.unwrap_or(self.scope.status);
+                self.exit = Some(exit);
+                exit
+            }
+            None => code
+        }
+    }
+
+    /// Runs a pipeline with calls to declared functions among its commands. As with builtins, the output of
+    /// a function is buffered before being fed into the next command, and the commands between the functions
+    /// are run by the host. A function cannot read the output of the command before it.
+    fn run_pipeline(&mut self, pipeline: &Pipeline) -> i32 {
+        let commands = &pipeline.commands;
+        let mut input: Option<String> = None;
+        let mut code = 0;
+        let mut start = 0;
+        while start < commands.len() && self.stopped().is_none() {
+            let is_function = self.functions.contains_key(&commands[start].call.name);
+            let end = match is_function {
+                true => start + 1,
+                false => commands[start..].iter().position(|f| self.functions.contains_key(&f.call.name)).map_or(commands.len(), |f| start + f)
+            };
+            // the output of the last command goes wherever the output of the pipeline goes.
+            let capture = end < commands.len() || self.output.is_some();
+            let (stage_code, output) = if is_function && input.is_some() {
+                eprintln!("{}: functions cannot read the output of a pipeline", commands[start].call.name);
+                (1, String::new())
+            } else if is_function && capture {
+                self.capture(|f| f.call(&commands[start]))
+            } else if is_function {
+                (self.call(&commands[start]), String::new())
+            } else {
+                let stages = commands[start..end].iter().map(|f| self.substitute_command(f)).collect::<Vec<Command>>();
+                let (code, output) = match input.take() {
+                    Some(input) => self.host.execute_piped(&stages, &self.scope, input, capture),
+                    None if capture => self.host.capture(&Expr::Pipeline(Pipeline { commands: stages }), &self.scope),
+                    None => (self.host.execute(&Expr::Pipeline(Pipeline { commands: stages }), &self.scope), String::new())
+                };
+                (self.finish_host(code), output)
+            };
+            code = stage_code;
+            input = Some(output);
+            start = end;
+        }
+        if let (Some(output), Some(last)) = (&mut self.output, input) {
+            output.push_str(&last);
+        }
+        code
+    }
+
+    /// Calls a declared function. The standard output of the call may be redirected to a file,
+    /// in which case it is written to the file once the call returns.
+    fn call(&mut self, command: &Command) -> i32 {
+        let name = &command.call.name;
+        let mut target = None;
+        // as with other commands, every file is created, but only the last one is written to.
+        for redirect in &command.redirects {
+            let Redirect::Stdout { path, append } = redirect else {
+                eprintln!("{}: functions can only redirect their standard output, with `>` or `>>`", name);
+                return 1;
+            };
+            let path = self.expand(path);
+            match OpenOptions::new().write(true).create(true).append(*append).truncate(!*append).open(&path) {
+                Ok(file) => target = Some((path, file)),
+                Err(err) => {
+                    eprintln!("{}: {}: {}", name, path, err);
+                    return 1;
+                }
+            }
+        }
+        let Some((path, mut file)) = target else {
+            return self.invoke(command);
+        };
+        let (code, output) = self.capture(|f| f.invoke(command));
+        if let Err(err) = file.write_all(output.as_bytes()) {
+            eprintln!("{}: {}: {}", name, path, err);
+            return 1;
+        }
+        code
+    }
+
+    /// Runs the body of a declared function, binding its formal arguments in a new frame.
+    fn invoke(&mut self, command: &Command) -> i32 {
+        let function = self.functions[&command.call.name].clone();
+        if command.call.args.len() != function.args.len() {
+            eprintln!("{}: expected {} argument(s), but {} were given", function.name, function.args.len(), command.call.args.len());
+            return 2;
+        }
+        if self.depth >= MAX_CALL_DEPTH {
+            eprintln!("{}: maximum call depth of {} exceeded", function.name, MAX_CALL_DEPTH);
+            return 1;
+        }
+        let mut frame = HashMap::new();
+        for (formal, word) in function.args.iter().zip(&command.call.args) {
+            let value = self.expand(word);
+            if let Err(message) = check_type(&value, &formal.r#type) {
+                eprintln!("{}: argument `{}` {}", function.name, formal.identifier, message);
+                return 2;
+            }
+            frame.insert(formal.identifier.clone(), value);
+        }
+        // a `break` in the body cannot stop a loop around the call.
+        let loops = std::mem::take(&mut self.loops);
+        self.depth += 1;
+        let caller = self.scope.enter(frame);
+        let code = self.run_statements(&function.body.statements);
+        self.scope.leave(caller);
+        self.depth -= 1;
+        self.loops = loops;
+        code
+    }
+}
+
+/// Returns the path of the redirection, if it has one.
+fn redirect_path(redirect: &Redirect) -> Option<&String> {
+    match redirect {
+        Redirect::Stdin(path) | Redirect::Stdout { path, .. } | Redirect::Stderr { path, .. } | Redirect::Both { path, .. } => Some(path),
+        Redirect::StderrToStdout => None
+    }
+}
+
+/// Checks that the argument can be used as a value of the given type.
+fn check_type(value: &str, r#type: &str) -> Result<(), String> {
+    match DeaconType::try_from(r#type) {
+        Ok(DeaconType::String) => Ok(()),
+        Ok(DeaconType::Int) if value.parse::<i64>().is_ok() => Ok(()),
+        Ok(DeaconType::Bool) if value == "true" || value == "false" => Ok(()),
+        Ok(DeaconType::Null) if value.is_empty() => Ok(()),
+        Ok(DeaconType::Tuple(_)) => Err("has a tuple type, which cannot be passed to a function yet".to_string()),
+        Ok(_) => Err(format!("is not a valid `{}`: {}", r#type, value)),
+        Err(_) => Err(format!("has an unknown type `{}`", r#type))
+    }
+}
@@ -1,110 +1,98 @@
-#![feature(exact_size_is_empty)]
-
 use std::fs::File;
-use std::io::Read;
+use std::io;
+use std::io::{Read, Write};
 use std::path::Path;
-use ariadne::{Label, Source};
-use nom::error::VerboseErrorKind;
-use deacon_parse::function::*;
+use ariadne::{Config, Label, Report, ReportKind, Source};
+use deacon_parse::ast::Script;
+use deacon_parse::error::ParseError;
+use deacon_parse::parse_script;
+use crate::interpreter::{Host, Interpreter};
+
+pub mod interpreter;
+pub mod scope;
+
+/// Runs the given script with the given interpreter, returning the exit code of its last statement.
+/// The script is [linted](lint_script) first, so nothing is run if it has any parsing errors;
+/// in that case, or if the script cannot be read, [`None`] is returned.
+///
+/// Scripts can be run in two ways:
+/// ```sh
+/// # starts a subshell child process with its own interpreter,
+/// # so `export` is a no-op
+/// deacon --run ./hello.dc
+///
+/// # runs the script in the current process; its exported
+/// # functions become available to the current shell
+/// using ./hello.dc
+/// ```
+pub fn run_script<H: Host>(file: impl AsRef<Path>, interpreter: &mut Interpreter<H>) -> Option<i32> {
+    let script = lint_script(file)?;
+    Some(interpreter.run(&script))
+}
 
-// /// Runs the given script. Callers **must** lint the script for errors
-// /// before running; not doing so can cause side effects for
-// /// the current environment (if the current environment is **`using`** the
-// /// given script).
-// /// ```sh
-// /// # equivalent to `run_script("hello.dc", false);`
-// /// # because it starts a subshell child process
-// /// # `export` will be no-op
-// /// deacon --run ./hello.dc
-// ///
-// /// # equivalent to `run_script("hello.dc", true);`
-// /// # because it runs the script in the current process
-// /// # export will actually have an effect here
-// /// using ./hello.dc
-// /// ```
-// pub fn run_script(file: impl AsRef<Path>, using: bool) -> io::Result<()> {
-//     let mut file = File::open(file)?;
-//     let mut input = String::new();
-//     file.read_to_string(&mut input)?;
-//     let input = input.as_str();
-//     deacon_parse::function::parse_func_declaration()
-// }
+/// Runs the given source with the given interpreter, returning the exit code of its last statement.
+/// If the source has any parsing errors, they are reported under the given name, nothing is run
+/// and [`None`] is returned.
+pub fn run_source<H: Host>(name: &str, source: &str, interpreter: &mut Interpreter<H>) -> Option<i32> {
+    match parse_script(source) {
+        Ok(script) => Some(interpreter.run(&script)),
+        Err(err) => {
+            report_parse_error(name, source, &err);
+            None
+        }
+    }
+}
 
-pub fn lint_script(file: impl AsRef<Path>) -> Option<(Vec<Function>, Vec<String>)> {
+/// Lints the given script, reporting any parsing errors to the standard error stream.
+/// If the script is valid, its [syntax tree](Script) is returned.
+pub fn lint_script(file: impl AsRef<Path>) -> Option<Script> {
     let path = file.as_ref();
     let mut file = File::open(path).ok()?;
     let mut input = String::new();
     file.read_to_string(&mut input).ok()?;
-    let full_input = input.clone();
-    let mut function_vec = vec![];
-    let mut statement_vec = vec![];
-    loop {
-        if input.trim().is_empty() {
-            break
-        }
-        match parse_func_declaration(&input) {
-            Ok((func, remainder)) => {
-                function_vec.push(func);
-                let remainder = remainder.trim();
-                input = remainder.to_string();
-                continue
-            }
-            Err(err) => {
-                match err {
-                    nom::Err::Incomplete(e) => {
-                        if !e.is_known() {
-                            // we now know it's a statement and not a function.
-                            let statement = input.trim().split_once("\n")
-                                .map(|f| f.0.trim())
-                                .unwrap_or(input.as_str())
-                                .trim();
-                            if statement.is_empty() {
-                                break
-                            }
-                            statement_vec.push(statement.to_string());
-                            input = input.replacen(statement, "", 1);
-                            continue
-                        } else {
-                            println!("Incomplete!");
-                            break
-                        }
-                    }
-                    nom::Err::Error(e) => {
-                        for(affected, kind) in e.errors {
-                            match kind {
-                                VerboseErrorKind::Context(ctx) => {
-                                    println!("Context: {}", ctx);
-                                }
-                                VerboseErrorKind::Char(expected) => {
-                                    let pb = path.to_path_buf();
-                                    let pb2 = pb.file_name().unwrap().to_string_lossy();
-                                    let name = pb2.as_ref();
-                                    let mut b = ariadne::Report::build(ariadne::ReportKind::Error, name, full_input.find(affected).unwrap())
-                                        .with_message(format!("Expected '{}'.", expected.escape_default()))
-                                        .with_label(Label::new((name, 4..7)));
-                                    if expected.escape_default().to_string() == "\\n" {
-                                        b.set_note("The '{' character must be followed by a newline, and the '}' character must be after another newline.");
-                                    }
-                                    let _ = b.with_code(0) // parsing error is E[0] b
-                                        .finish()
-                                        .eprint((name, Source::from(&input)));
-                                }
-                                VerboseErrorKind::Nom(error) => {
-                                    println!("ErrorKind: {:?}", error);
-                                }
-                            }
-                        }
-                        break
-                    }
-                    nom::Err::Failure(e) => {
-                        // CONFIRMED: we're on correct branch!
-                        eprintln!("Oh no!\n{}", nom::error::convert_error(input.as_str(), e));
-                    }
-                }
-            }
+    match parse_script(input.as_str()) {
+        Ok(script) => Some(script),
+        Err(err) => {
+            let name = path.file_name().map_or(path.to_string_lossy(), |f| f.to_string_lossy());
+            report_parse_error(&name, &input, &err);
+            None
         }
     }
-    Some((function_vec, statement_vec))
+}
+
+/// Reports a parsing error in the given source to the standard error stream,
+/// labelling the exact range of the source the error refers to.
+pub fn report_parse_error(name: &str, source: &str, err: &ParseError) {
+    let _ = write_parse_error(name, source, err, true, io::stderr());
+}
+
+/// Writes the report of a parsing error in the given source, like [`report_parse_error`], with or without colours.
+///
+/// ```
+/// # use deacon_engine::write_parse_error;
+/// let source = "echo é |";
+/// let err = deacon_parse::parse_script(source).unwrap_err();
+/// let mut output = vec![];
+/// write_parse_error("command", source, &err, false, &mut output).unwrap();
+/// let output = String::from_utf8(output).unwrap();
+/// // the label is under the `|`, even though `é` takes two bytes.
+/// let line = output.lines().find(|f| f.contains(source)).unwrap();
+/// let label = output.lines().find(|f| f.contains('┬')).unwrap();
+/// assert_eq!(line.chars().position(|f| f == '|'), label.chars().position(|f| f == '┬'));
+/// ```
+pub fn write_parse_error(name: &str, source: &str, err: &ParseError, color: bool, output: impl Write) -> io::Result<()> {
+    // the span of the error is in bytes, but ariadne counts characters.
+    let chars = |offset: usize| source[..offset].chars().count();
+    let span = chars(err.span.start)..chars(err.span.end);
+    let mut report = Report::build(ReportKind::Error, name, span.start)
+        .with_config(Config::default().with_color(color))
+        .with_code(0) // parsing error is E[0]
+        .with_message(format!("Failed to parse {} at {}:{}", name, err.line, err.column))
+        .with_label(Label::new((name, span)).with_message(&err.message));
+    if err.message == "unclosed `{`" {
+        report.set_note("Every '{' must be closed by a '}'.");
+    }
+    report.finish().write((name, Source::from(source)), output)
 }
 
 /// The `Either` type.
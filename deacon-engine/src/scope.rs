@@ -0,0 +1,118 @@
+//! The variables of a running script.
+
+use std::collections::HashMap;
+
+/// The variables visible at some point of a script. Each function call, block and iteration of a `for` loop
+/// has its own frame, which is dropped at its end. `let` [binds](Scope::set) a variable in the innermost frame,
+/// replacing any previous binding of that frame, while a reassignment such as `$x = 2` [changes](Scope::assign)
+/// the binding in the innermost frame which has it, even if that frame is an outer one.
+/// A function call only sees the outermost frame, that of the script, and not the frames of its caller.
+/// The exit code of the last statement is available as `$?`.
+#[derive(Debug, Clone)]
+pub struct Scope {
+    frames: Vec<HashMap<String, String>>,
+    pub(crate) status: i32
+}
+
+impl Default for Scope {
+    fn default() -> Self {
+        Scope { frames: vec![HashMap::new()], status: 0 }
+    }
+}
+
+impl Scope {
+    /// Creates a scope with a single, empty frame.
+    pub fn new() -> Scope {
+        Scope::default()
+    }
+
+    /// Returns the value of the given variable, looking from the innermost frame outwards.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.frames.iter().rev().find_map(|f| f.get(name)).map(String::as_str)
+    }
+
+    /// Binds the given variable in the innermost frame.
+    pub fn set(&mut self, name: impl ToString, value: impl ToString) {
+        self.frames.last_mut().unwrap().insert(name.to_string(), value.to_string());
+    }
+
+    /// Changes the value of the given variable in the innermost frame which binds it,
+    /// returning whether any frame does.
+    ///
+    /// ```
+    /// # use deacon_engine::scope::Scope;
+    /// let mut scope = Scope::new();
+    /// scope.set("x", 1);
+    /// assert!(scope.assign("x", 2));
+    /// assert_eq!(scope.get("x"), Some("2"));
+    /// assert!(!scope.assign("y", 3));
+    /// assert_eq!(scope.get("y"), None);
+    /// ```
+    pub fn assign(&mut self, name: &str, value: impl ToString) -> bool {
+        match self.frames.iter_mut().rev().find_map(|f| f.get_mut(name)) {
+            Some(binding) => {
+                *binding = value.to_string();
+                true
+            }
+            None => false
+        }
+    }
+
+    /// Returns the exit code of the last statement, that is, `$?`.
+    pub fn status(&self) -> i32 {
+        self.status
+    }
+
+    pub(crate) fn push(&mut self) {
+        self.frames.push(HashMap::new());
+    }
+
+    pub(crate) fn pop(&mut self) {
+        self.frames.pop();
+    }
+
+    /// Hides every frame but the outermost one under a new frame for a function call,
+    /// returning the hidden frames so that [Scope::leave] can restore them.
+    pub(crate) fn enter(&mut self, frame: HashMap<String, String>) -> Vec<HashMap<String, String>> {
+        let caller = self.frames.split_off(1);
+        self.frames.push(frame);
+        caller
+    }
+
+    /// Drops the frames of a function call and restores the frames of its caller.
+    pub(crate) fn leave(&mut self, caller: Vec<HashMap<String, String>>) {
+        self.frames.truncate(1);
+        self.frames.extend(caller);
+    }
+
+    /// Substitutes every `$name` in the text whose variable is defined, and `$?` with the exit code of
+    /// the last statement. Undefined variables are kept as-is.
+    ///
+    /// ```
+    /// # use deacon_engine::scope::Scope;
+    /// let mut scope = Scope::new();
+    /// scope.set("name", "world");
+    /// assert_eq!(scope.substitute("hello $name, $other ($?)"), "hello world, $other (0)");
+    /// ```
+    pub fn substitute(&self, text: &str) -> String {
+        let mut output = String::new();
+        let mut rest = text;
+        while let Some(index) = rest.find('$') {
+            output.push_str(&rest[..index]);
+            let after = &rest[index + 1..];
+            if let Some(after) = after.strip_prefix('?') {
+                output.push_str(&self.status.to_string());
+                rest = after;
+                continue
+            }
+            let length = after.find(|c: char| !(c.is_alphabetic() || c == '_')).unwrap_or(after.len());
+            match self.get(&after[..length]) {
+                Some(value) if length > 0 => output.push_str(value),
+                _ => output.push_str(&rest[index..index + 1 + length])
+            }
+            rest = &after[length..];
+        }
+        output.push_str(rest);
+        output
+    }
+}
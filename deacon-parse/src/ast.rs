@@ -0,0 +1,145 @@
+//! The Deacon abstract syntax tree.
+//!
+//! Every consumer of Deacon source code (the engine, the linter and the REPL) works on
+//! the tree produced by [`parse_script`](crate::parse_script) instead of on raw lines.
+
+use crate::function::{Call, Function};
+use crate::variable::Variable;
+
+/// A parsed Deacon script. This is simply a list of top-level [statements](Statement).
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Script {
+	pub statements: Vec<Statement>
+}
+
+impl Script {
+	/// Returns an iterator over the functions declared at the top level of this script.
+	pub fn functions(&self) -> impl Iterator<Item = &Function> {
+		self.statements.iter().filter_map(|f| match f {
+			Statement::FunctionDecl(func) => Some(func),
+			_ => None
+		})
+	}
+}
+
+/// A single statement.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Statement {
+	/// An expression which is evaluated for its side effects, such as a call.
+	Expr(Expr),
+	/// A variable declaration, in the form `let $x = value`.
+	VariableDecl(Variable),
+	/// A variable reassignment, in the form `$x = value`.
+	VariableReassignment(Variable),
+	/// A function declaration.
+	FunctionDecl(Function),
+	/// A block of statements delimited by `{` and `}`.
+	Block(Block),
+	/// An `if` statement, along with its `else if` and `else` branches.
+	If(If),
+	/// A `while` loop.
+	While(While),
+	/// A `for` loop.
+	For(For),
+	/// `break`, which stops the innermost loop.
+	Break,
+	/// `continue`, which skips the rest of the current iteration of the innermost loop.
+	Continue
+}
+
+/// An expression.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Expr {
+	/// A single command.
+	Command(Command),
+	/// Two or more commands connected by `|`.
+	Pipeline(Pipeline),
+	/// `left && right`: evaluates the right expression only if the left one succeeds.
+	And(Box<Expr>, Box<Expr>),
+	/// `left || right`: evaluates the right expression only if the left one fails.
+	Or(Box<Expr>, Box<Expr>),
+	/// `pipeline &`: starts the pipeline as a background job, without waiting for it.
+	Background(Pipeline)
+}
+
+/// A call to a builtin, function or process, along with its redirections.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Command {
+	pub call: Call,
+	pub redirects: Vec<Redirect>
+}
+
+/// A redirection of one of a command's standard streams. Paths are words as written, which are expanded
+/// like arguments when the command runs.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Redirect {
+	/// `< path`: reads the standard input from the given file.
+	Stdin(String),
+	/// `> path` or `>> path`: writes the standard output to the given file.
+	Stdout { path: String, append: bool },
+	/// `2> path` or `2>> path`: writes the standard error to the given file.
+	Stderr { path: String, append: bool },
+	/// `&> path` or `&>> path`: writes both the standard output and error to the given file.
+	Both { path: String, append: bool },
+	/// `2>&1`: writes the standard error to wherever the standard output goes.
+	StderrToStdout
+}
+
+/// A list of commands where the standard output of each command is connected
+/// to the standard input of the next command.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Pipeline {
+	pub commands: Vec<Command>
+}
+
+/// A list of statements delimited by `{` and `}`, such as the body of a function.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Block {
+	pub statements: Vec<Statement>
+}
+
+/// An `if` statement. The block of the first branch whose condition holds is run;
+/// if no condition holds, the `else` block is run, if any.
+#[derive(Debug, PartialEq, Clone)]
+pub struct If {
+	/// The conditions and blocks of the `if` branch and every `else if` branch, in order.
+	pub branches: Vec<(Condition, Block)>,
+	/// The block of the `else` branch.
+	pub otherwise: Option<Block>
+}
+
+/// The condition of an [`if`](If).
+#[derive(Debug, PartialEq, Clone)]
+pub enum Condition {
+	/// `true` or `false`.
+	Bool(bool),
+	/// `left == right` or `left != right`, which compares the words after they are expanded.
+	Compare { left: String, right: String, equal: bool },
+	/// An expression, such as a call, which holds if its exit code is 0.
+	Expr(Expr)
+}
+
+/// A `while` loop, which runs its body as long as its condition holds.
+#[derive(Debug, PartialEq, Clone)]
+pub struct While {
+	pub condition: Condition,
+	pub body: Block
+}
+
+/// A `for` loop, which runs its body once for every item, with the variable bound to the item.
+#[derive(Debug, PartialEq, Clone)]
+pub struct For {
+	/// The name of the variable, without the `$`.
+	pub variable: String,
+	pub items: Items,
+	pub body: Block
+}
+
+/// The items of a [`for`](For) loop.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Items {
+	/// Words, each of which is an item, or expands to the matching paths if it is a glob.
+	Words(Vec<String>),
+	/// `$(expression)`: each line of the standard output of the expression is an item.
+	Output(Expr)
+}
@@ -0,0 +1,186 @@
+//! Pipelines and other compound commands.
+
+use nom::Slice;
+use crate::Span;
+use crate::ast::{Command, Expr, Pipeline, Redirect};
+use crate::error::ParseError;
+use crate::function::Call;
+use crate::lexer::{tokenize, Token, TokenKind};
+
+/// Parses a command list. The syntax for a command list is:
+/// ```sh
+/// PIPELINE (('&&' | '||') PIPELINE)*
+/// ```
+/// The exit status of each pipeline decides whether the next one runs: the right side of `&&`
+/// only runs if the left side succeeds, and the right side of `||` only runs if the left side fails.
+/// Both operators have the same precedence and are evaluated from left to right, so
+/// ```sh
+/// $ cargo build && ./target/debug/app || echo failed
+/// ```
+/// prints `failed` if either the build or the application fails.
+///
+/// Sequential lists separated by `;` are split into separate statements by [`parse_script`](crate::parse_script).
+///
+/// ```
+/// # use deacon_parse::command::parse_list;
+/// # use deacon_parse::ast::*;
+/// assert!(matches!(parse_list("ls"), Ok(Expr::Command(_))));
+/// assert!(matches!(parse_list("a && b || c"), Ok(Expr::Or(left, _)) if matches!(*left, Expr::And(_, _))));
+/// assert!(matches!(parse_list("a | b && c"), Ok(Expr::And(left, _)) if matches!(*left, Expr::Pipeline(_))));
+/// assert_eq!(parse_list("a &&").unwrap_err().span, 2..4);
+/// ```
+pub fn parse_list<'a>(input: impl Into<Span<'a>>) -> Result<Expr, ParseError> {
+	let input = input.into();
+	let tokens = tokenize(input)?;
+	parse_list_tokens(input, &tokens)
+}
+
+/// Parses a [command list](parse_list) from the tokens of the given input.
+pub(crate) fn parse_list_tokens<'a>(input: Span<'a>, tokens: &[Token<'a>]) -> Result<Expr, ParseError> {
+	let mut parts = split_tokens(tokens, |f| f == TokenKind::And || f == TokenKind::Or).into_iter();
+	// the first part never has an operator.
+	let (first, _) = parts.next().unwrap();
+	let mut expr = parse_list_operand(input, first, tokens.first())?;
+	for (part, operator) in parts {
+		let operator = operator.unwrap();
+		let right = Box::new(parse_list_operand(input, part, Some(operator))?);
+		expr = if operator.kind == TokenKind::And {
+			Expr::And(Box::new(expr), right)
+		} else {
+			Expr::Or(Box::new(expr), right)
+		};
+	}
+	Ok(expr)
+}
+
+fn parse_list_operand<'a>(input: Span<'a>, tokens: &[Token<'a>], operator: Option<&Token<'a>>) -> Result<Expr, ParseError> {
+	if tokens.is_empty() {
+		Err(error(input, operator, "expected a call on both sides of `&&` and `||`"))
+	} else {
+		parse_pipeline_tokens(input, tokens)
+	}
+}
+
+/// Parses a pipeline. The syntax for a pipeline is:
+/// ```sh
+/// COMMAND ('|' COMMAND)*
+/// ```
+/// Each command's standard output is connected to the standard input of the next command, so
+/// ```sh
+/// $ ls | grep Cargo
+/// ```
+/// runs the `ls` builtin and feeds its output into the `grep` process.
+///
+/// If there is only one command, this function returns an [`Expr::Command`] instead of a pipeline.
+///
+/// ```
+/// # use deacon_parse::command::parse_pipeline;
+/// # use deacon_parse::ast::*;
+/// assert!(matches!(parse_pipeline("ls"), Ok(Expr::Command(_))));
+/// assert!(matches!(parse_pipeline("ls | grep Cargo"), Ok(Expr::Pipeline(p)) if p.commands.len() == 2));
+/// assert!(matches!(parse_pipeline("echo \"a | b\""), Ok(Expr::Command(_))));
+/// assert!(parse_pipeline("ls |").is_err());
+/// ```
+pub fn parse_pipeline<'a>(input: impl Into<Span<'a>>) -> Result<Expr, ParseError> {
+	let input = input.into();
+	let tokens = tokenize(input)?;
+	parse_pipeline_tokens(input, &tokens)
+}
+
+fn parse_pipeline_tokens<'a>(input: Span<'a>, tokens: &[Token<'a>]) -> Result<Expr, ParseError> {
+	let mut commands = vec![];
+	for (stage, operator) in split_tokens(tokens, |f| f == TokenKind::Pipe) {
+		if stage.is_empty() {
+			return Err(error(input, operator.or(tokens.first()), "expected a call on both sides of `|`"));
+		}
+		commands.push(parse_command_tokens(input, stage)?);
+	}
+	if commands.len() == 1 {
+		Ok(Expr::Command(commands.remove(0)))
+	} else {
+		Ok(Expr::Pipeline(Pipeline { commands }))
+	}
+}
+
+/// Parses a command, which is a [call](parse_call) with any number of redirections:
+/// ```sh
+/// CALL (('<' | '>' | '>>' | '2>' | '2>>' | '&>' | '&>>') FILE | '2>&1')*
+/// ```
+/// Redirections may appear anywhere after the call's name, so both
+/// `ls > out.txt` and `ls >out.txt` write the output of `ls` to `out.txt`.
+/// Operators inside quotes are treated as regular arguments. File names are kept as written,
+/// like the words of the call, and are expanded when the command runs.
+///
+/// ```
+/// # use deacon_parse::command::parse_command;
+/// # use deacon_parse::ast::*;
+/// let command = parse_command("ls >> \"my file.txt\" 2>&1").unwrap();
+/// assert_eq!(command.call.name, "ls");
+/// assert_eq!(command.redirects, vec![
+///     Redirect::Stdout { path: "\"my file.txt\"".to_string(), append: true },
+///     Redirect::StderrToStdout
+/// ]);
+/// assert!(parse_command("echo \">\"").unwrap().redirects.is_empty());
+/// assert!(parse_command("ls >").is_err());
+/// ```
+pub fn parse_command<'a>(input: impl Into<Span<'a>>) -> Result<Command, ParseError> {
+	let input = input.into();
+	let tokens = tokenize(input)?;
+	parse_command_tokens(input, &tokens)
+}
+
+fn parse_command_tokens<'a>(input: Span<'a>, tokens: &[Token<'a>]) -> Result<Command, ParseError> {
+	let mut words = vec![];
+	let mut redirects = vec![];
+	let mut tokens = tokens.iter();
+	while let Some(token) = tokens.next() {
+		match token.kind {
+			TokenKind::Word => words.push(token.text.to_string()),
+			TokenKind::Redirect if token.text == "2>&1" => redirects.push(Redirect::StderrToStdout),
+			TokenKind::Redirect => {
+				let path = match tokens.next() {
+					Some(target) if target.kind == TokenKind::Word => target.text.to_string(),
+					_ => return Err(error(input, Some(token), "expected a file name after the redirection operator"))
+				};
+				let append = token.text.ends_with(">>");
+				redirects.push(match token.text {
+					"<" => Redirect::Stdin(path),
+					">" | ">>" => Redirect::Stdout { path, append },
+					"2>" | "2>>" => Redirect::Stderr { path, append },
+					_ => Redirect::Both { path, append }
+				});
+			}
+			_ => return Err(error(input, Some(token), "unexpected operator"))
+		}
+	}
+	if words.is_empty() {
+		return Err(error(input, None, "expected a call before the redirection operator"));
+	}
+	let name = words.remove(0);
+	Ok(Command { call: Call { name, args: words }, redirects })
+}
+
+/// Splits the tokens at every token whose kind matches the predicate,
+/// returning each part along with the separating token that precedes it.
+pub(crate) fn split_tokens<'t, 'a>(tokens: &'t [Token<'a>], is_separator: impl Fn(TokenKind) -> bool) -> Vec<(&'t [Token<'a>], Option<&'t Token<'a>>)> {
+	let mut parts = vec![];
+	let mut start = 0;
+	let mut separator = None;
+	for (index, token) in tokens.iter().enumerate() {
+		if is_separator(token.kind) {
+			parts.push((&tokens[start..index], separator));
+			separator = Some(token);
+			start = index + 1;
+		}
+	}
+	parts.push((&tokens[start..], separator));
+	parts
+}
+
+/// Creates an error pointing at the given token, or at the start of the input if there is none.
+pub(crate) fn error(input: Span, token: Option<&Token>, message: &'static str) -> ParseError {
+	match token {
+		Some(token) => ParseError::new(input.slice(token.span.start - input.location_offset()..), token.text.len(), message),
+		None => ParseError::new(input, 0, message)
+	}
+}
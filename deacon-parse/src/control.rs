@@ -0,0 +1,184 @@
+//! Control flow statements.
+
+use nom::Slice;
+use crate::{check_substitutions, parse_block, skip_whitespace, Span};
+use crate::ast::{Condition, For, If, Items, While};
+use crate::function::take_until_unbalanced;
+use crate::command::parse_list;
+use crate::error::ParseError;
+use crate::lexer::{tokenize, TokenKind};
+
+/// Parses an `if` statement, along with its `else if` and `else` branches. The syntax is:
+/// ```sh
+/// 'if' CONDITION '{'
+///     STATEMENT*
+/// '}' ('else' 'if' CONDITION '{'
+///     STATEMENT*
+/// '}')* ('else' '{'
+///     STATEMENT*
+/// '}')?
+/// ```
+/// The `{` of each branch must be on the same line as its condition. For the syntax of conditions,
+/// see [`parse_condition`].
+///
+/// ```
+/// # use deacon_parse::control::parse_if;
+/// # use deacon_parse::ast::*;
+/// let (remainder, r#if) = parse_if("if test -d src {\n    cd src\n} else if false {\n} else { ls }\necho done".into()).unwrap();
+/// assert_eq!(r#if.branches.len(), 2);
+/// assert_eq!(r#if.branches[1].0, Condition::Bool(false));
+/// assert_eq!(r#if.otherwise.unwrap().statements.len(), 1);
+/// assert_eq!(*remainder.fragment(), "\necho done");
+/// assert!(parse_if("if true\n{\n}".into()).is_err());
+/// ```
+pub fn parse_if(input: Span) -> Result<(Span, If), ParseError> {
+	let mut branches = vec![];
+	let mut input = input;
+	loop {
+		let (header, body) = split_header(input.slice("if".len()..), "the condition")?;
+		let condition = parse_condition(header)?;
+		let (remainder, block) = parse_block(body)?;
+		branches.push((condition, block));
+		let next = skip_whitespace(remainder);
+		if !is_keyword(next.fragment(), "else") {
+			return Ok((remainder, If { branches, otherwise: None }));
+		}
+		let after = skip_whitespace(next.slice("else".len()..));
+		if is_keyword(after.fragment(), "if") {
+			input = after;
+		} else if after.fragment().starts_with('{') {
+			let (remainder, block) = parse_block(after)?;
+			return Ok((remainder, If { branches, otherwise: Some(block) }));
+		} else {
+			return Err(ParseError::new(next, "else".len(), "expected `if` or `{` after `else`"));
+		}
+	}
+}
+
+/// Parses a `while` loop, which runs its body as long as its condition holds. The syntax is:
+/// ```sh
+/// 'while' CONDITION '{'
+///     STATEMENT*
+/// '}'
+/// ```
+/// For the syntax of conditions, see [`parse_condition`]. `break` stops the loop, and `continue`
+/// skips to the next check of the condition.
+///
+/// ```
+/// # use deacon_parse::control::parse_while;
+/// # use deacon_parse::ast::*;
+/// let (_, r#while) = parse_while("while not test -f done {\n    sleep 1\n}".into()).unwrap();
+/// assert!(matches!(r#while.condition, Condition::Expr(Expr::Command(_))));
+/// assert_eq!(r#while.body.statements.len(), 1);
+/// ```
+pub fn parse_while(input: Span) -> Result<(Span, While), ParseError> {
+	let (header, body) = split_header(input.slice("while".len()..), "the condition")?;
+	let condition = parse_condition(header)?;
+	let (remainder, body) = parse_block(body)?;
+	Ok((remainder, While { condition, body }))
+}
+
+/// Parses a `for` loop, which runs its body once for every item, with the variable bound to the item.
+/// The syntax is:
+/// ```sh
+/// 'for' '$' IDENTIFIER 'in' (WORD* | '$(' EXPRESSION ')') '{'
+///     STATEMENT*
+/// '}'
+/// ```
+/// Each word is an item, unless it is a glob such as `*.rs`, which expands to the matching paths.
+/// With `$(...)`, each line of the output of the expression is an item.
+///
+/// ```
+/// # use deacon_parse::control::parse_for;
+/// # use deacon_parse::ast::*;
+/// let (_, r#for) = parse_for("for $file in *.rs \"a b\" {\n    echo $file\n}".into()).unwrap();
+/// assert_eq!(r#for.variable, "file");
+/// assert_eq!(r#for.items, Items::Words(vec!["*.rs".to_string(), "\"a b\"".to_string()]));
+/// let (_, r#for) = parse_for("for $line in $(git ls-files | grep test) { echo $line }".into()).unwrap();
+/// assert!(matches!(r#for.items, Items::Output(Expr::Pipeline(_))));
+/// assert!(parse_for("for x in a {\n}".into()).is_err());
+/// ```
+pub fn parse_for(input: Span) -> Result<(Span, For), ParseError> {
+	let (header, body) = split_header(input.slice("for".len()..), "the items")?;
+	let header = skip_whitespace(header);
+	let length = header.fragment().find(|c: char| c.is_ascii_whitespace()).unwrap_or(header.fragment().len());
+	let variable = header.fragment()[..length].strip_prefix('$')
+		.filter(|f| !f.is_empty() && f.chars().all(|c| c.is_alphabetic() || c == '_'))
+		.ok_or_else(|| ParseError::new(header, length, "expected a variable such as `$x` after `for`"))?;
+	let rest = skip_whitespace(header.slice(length..));
+	if !is_keyword(rest.fragment(), "in") {
+		return Err(ParseError::new(rest, rest.fragment().trim_end().len(), "expected `in` after the variable"));
+	}
+	let list = skip_whitespace(rest.slice("in".len()..));
+	let tokens = tokenize(list)?;
+	check_substitutions(list, &tokens)?;
+	let items = if list.fragment().starts_with("$(") {
+		let (after, inner) = take_until_unbalanced('(', ')')(list.slice(2..)).ok()
+			.filter(|f| f.0.fragment().starts_with(')'))
+			.ok_or_else(|| ParseError::new(list, 2, "unclosed `$(`"))?;
+		let extra = skip_whitespace(after.slice(1..));
+		if !extra.fragment().is_empty() {
+			return Err(ParseError::new(extra, extra.fragment().trim_end().len(), "expected `{` after `$(...)`"));
+		}
+		Items::Output(parse_list(inner)?)
+	} else {
+		if let Some(operator) = tokens.iter().find(|f| f.kind != TokenKind::Word) {
+			let at = list.slice(operator.span.start - list.location_offset()..);
+			return Err(ParseError::new(at, operator.text.len(), "expected words or `$(...)` as the items"));
+		}
+		Items::Words(tokens.into_iter().map(|f| f.text.to_string()).collect())
+	};
+	let (remainder, body) = parse_block(body)?;
+	Ok((remainder, For { variable: variable.to_string(), items, body }))
+}
+
+/// Splits the rest of the line after a keyword into the header and the block which starts at the first `{`.
+/// The block must start on the same line as the keyword.
+fn split_header<'a>(input: Span<'a>, header: &str) -> Result<(Span<'a>, Span<'a>), ParseError> {
+	let line = input.slice(..input.fragment().find('\n').unwrap_or(input.fragment().len()));
+	let brace = tokenize(line)?.into_iter()
+		.find(|f| f.kind == TokenKind::Word && f.text.starts_with('{'))
+		.map(|f| f.span.start - line.location_offset())
+		.ok_or_else(|| ParseError::new(line.slice(line.fragment().len()..), 0, format!("expected `{{` after {}", header)))?;
+	Ok((line.slice(..brace), input.slice(brace..)))
+}
+
+/// Parses the condition of an `if` or a `while`, which is one of:
+/// - `true` or `false`;
+/// - a comparison of two words with `==` or `!=`, such as `$name == "world"`;
+/// - an [expression](crate::ast::Expr), such as a call, which holds if it exits with the exit code 0.
+///
+/// To negate a call, use the `not` builtin, as in `not test -d src`.
+///
+/// ```
+/// # use deacon_parse::control::parse_condition;
+/// # use deacon_parse::ast::*;
+/// assert_eq!(parse_condition("true"), Ok(Condition::Bool(true)));
+/// assert_eq!(parse_condition("$x != \"a b\""), Ok(Condition::Compare { left: "$x".to_string(), right: "\"a b\"".to_string(), equal: false }));
+/// assert!(matches!(parse_condition("cargo build && cargo test"), Ok(Condition::Expr(Expr::And(_, _)))));
+/// assert!(parse_condition(" ").is_err());
+/// ```
+pub fn parse_condition<'a>(input: impl Into<Span<'a>>) -> Result<Condition, ParseError> {
+	let input = skip_whitespace(input.into());
+	let input = input.slice(..input.fragment().trim_end().len());
+	let tokens = tokenize(input)?;
+	check_substitutions(input, &tokens)?;
+	if tokens.iter().any(|f| f.kind != TokenKind::Word) {
+		return parse_list(input).map(Condition::Expr);
+	}
+	match &tokens[..] {
+		[] => Err(ParseError::new(input, 0, "expected a condition")),
+		[word] if word.text == "true" || word.text == "false" => Ok(Condition::Bool(word.text == "true")),
+		[left, operator, right] if operator.text == "==" || operator.text == "!=" => Ok(Condition::Compare {
+			left: left.text.to_string(),
+			right: right.text.to_string(),
+			equal: operator.text == "=="
+		}),
+		_ => parse_list(input).map(Condition::Expr)
+	}
+}
+
+/// Returns whether the input starts with the given keyword, as a word of its own.
+pub(crate) fn is_keyword(input: &str, keyword: &str) -> bool {
+	matches!(input.strip_prefix(keyword), Some(rest) if rest.is_empty() || rest.starts_with(|c: char| c.is_ascii_whitespace() || c == '{'))
+}
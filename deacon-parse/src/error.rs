@@ -0,0 +1,71 @@
+//! Parsing errors.
+
+use std::fmt;
+use std::ops::Range;
+use nom::error::{ErrorKind, VerboseError, VerboseErrorKind};
+use crate::Span;
+
+/// A parsing error, along with where in the input it happened.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ParseError {
+	/// The byte range of the input this error refers to.
+	pub span: Range<usize>,
+	/// The line this error starts on, starting from 1.
+	pub line: u32,
+	/// The column this error starts on in characters, starting from 1.
+	pub column: usize,
+	pub message: String
+}
+
+impl ParseError {
+	/// Creates an error which starts at the given span and is `length` bytes long.
+	/// The length is capped to the length of the span.
+	pub fn new(at: Span, length: usize, message: impl ToString) -> ParseError {
+		let length = length.min(at.fragment().len());
+		ParseError {
+			span: at.location_offset()..at.location_offset() + length,
+			line: at.location_line(),
+			column: at.get_utf8_column(),
+			message: message.to_string()
+		}
+	}
+}
+
+impl fmt::Display for ParseError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}:{}: {}", self.line, self.column, self.message)
+	}
+}
+
+impl std::error::Error for ParseError {}
+
+impl<'a> From<VerboseError<Span<'a>>> for ParseError {
+	fn from(err: VerboseError<Span<'a>>) -> ParseError {
+		// the first error is the innermost one, which is the most precise.
+		let (at, kind) = &err.errors[0];
+		let message = match kind {
+			VerboseErrorKind::Context(ctx) => ctx.to_string(),
+			VerboseErrorKind::Char(expected) => format!("expected '{}'", expected.escape_default()),
+			VerboseErrorKind::Nom(ErrorKind::Alpha) => "expected an identifier".to_string(),
+			VerboseErrorKind::Nom(ErrorKind::TakeWhile1) => "expected a value".to_string(),
+			VerboseErrorKind::Nom(kind) => format!("unexpected input ({})", kind.description().to_lowercase())
+		};
+		// point at the first character the error refers to.
+		let length = at.fragment().chars().next().map_or(0, char::len_utf8);
+		ParseError::new(*at, length, message)
+	}
+}
+
+impl<'a> From<nom::Err<VerboseError<Span<'a>>>> for ParseError {
+	fn from(err: nom::Err<VerboseError<Span<'a>>>) -> ParseError {
+		match err {
+			nom::Err::Error(e) | nom::Err::Failure(e) => e.into(),
+			nom::Err::Incomplete(_) => ParseError {
+				span: 0..0,
+				line: 1,
+				column: 1,
+				message: "unexpected end of input".to_string()
+			}
+		}
+	}
+}
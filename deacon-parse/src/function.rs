@@ -1,14 +1,17 @@
 //! Function declaration handlers and tables.
 
 use std::hint::unreachable_unchecked;
-use nom::bytes::complete::{tag, take_while};
-use nom::character::complete::{alpha1, char, multispace0};
+use nom::bytes::complete::tag;
+use nom::character::complete::{alpha1, char, multispace0, multispace1};
 use nom::Err::Incomplete;
-use nom::error::{ErrorKind, ParseError, VerboseError};
-use nom::{IResult, Needed};
+use nom::error::{ErrorKind, VerboseError};
+use nom::{InputTake, IResult, Needed, Slice};
 use nom::multi::separated_list0;
-use nom::sequence::{delimited, terminated, tuple};
-use crate::alpha_underscore_1;
+use nom::sequence::{terminated, tuple};
+use crate::{alpha_underscore_1, skip_whitespace, Span};
+use crate::ast::Block;
+use crate::error::ParseError;
+use crate::lexer::tokenize;
 
 /// Parses a function. The syntax for a function is:
 /// ```sh
@@ -33,61 +36,91 @@ use crate::alpha_underscore_1;
 /// using the exclamation-escape syntax, in this case `!func`.
 ///
 /// This function, compared to other functions, returns a layer of [metadata](nom::Err) since this
-/// parser uses **streaming** parsers (since it has to handle newlines).
-pub fn parse_func_declaration(input: &str) -> Result<(Function, &str), nom::Err<VerboseError<&str>>> {
+/// parser uses **streaming** parsers (since it has to handle newlines). If the input is not a function
+/// declaration at all, [`nom::Err::Incomplete`] is returned with [`Needed::Unknown`]. If the input is
+/// the start of a function declaration whose body is missing or not closed yet, [`nom::Err::Incomplete`]
+/// is returned with [`Needed::Size`], so that more lines can be read.
+///
+/// ```
+/// # use deacon_parse::function::parse_func_declaration;
+/// # use nom::Needed;
+/// assert!(matches!(parse_func_declaration("echo hi"), Err(nom::Err::Incomplete(Needed::Unknown))));
+/// assert!(matches!(parse_func_declaration("func x() {\n    echo hi"), Err(nom::Err::Incomplete(Needed::Size(_)))));
+/// assert!(matches!(parse_func_declaration("func x() {\n    echo hi\n}"), Ok(_)));
+/// assert!(matches!(parse_func_declaration("func x() echo hi"), Err(nom::Err::Error(_))));
+/// assert!(matches!(parse_func_declaration("export func x() {\n}"), Ok((f, _)) if f.exported));
+/// ```
+pub fn parse_func_declaration<'a>(input: impl Into<Span<'a>>) -> Result<(Function, Span<'a>), nom::Err<ParseError>> {
 
 	// parsing header start
-	let mut input = input;
-	let export_tag = tag::<_, &str, ()>("export")(input);
+	let mut input = input.into();
+	let export_tag = terminated(tag::<_, Span, ()>("export"), multispace1)(input);
 	let is_exported = export_tag.is_ok();
 	if is_exported {
 		input = export_tag.unwrap().0;
 	}
-	let input = input.trim();
-	let header = tag::<_, &str, VerboseError<&str>>("func")(input)
+	let input = skip_whitespace(input);
+	let header = terminated(tag::<_, Span, VerboseError<Span>>("func"), multispace1)(input)
 		.map_err(|f| match f {
 			Incomplete(_) => unsafe { unreachable_unchecked() }
 			nom::Err::Error(_) | nom::Err::Failure(_) => {
 				Incomplete(Needed::Unknown) // returning incomplete gives a telltale sign of a func decl.; Needed::Unknown is going to be our magic value.
 			}
-		})?.0.trim();
-	let (args, name) = terminated(alpha_underscore_1::<&str, VerboseError<&str>>, tag::<_, &str, VerboseError<&str>>("("))(header)?;
+		})?.0;
+	let (args, name) = terminated(alpha_underscore_1::<Span, VerboseError<Span>>, tag::<_, Span, VerboseError<Span>>("("))(header)
+		.map_err(|f| f.map(ParseError::from))?;
 	let (code_block, args) = terminated(
 		separated_list0(
-			tuple((char::<&str, VerboseError<&str>>(','), multispace0::<&str, VerboseError<&str>>)),
-			tuple((alpha_underscore_1::<&str, VerboseError<&str>>, multispace0::<&str, VerboseError<&str>>, char::<&str, VerboseError<&str>>(':'), multispace0::<&str, VerboseError<&str>>, alpha1::<&str, VerboseError<&str>>))
+			tuple((char::<Span, VerboseError<Span>>(','), multispace0::<Span, VerboseError<Span>>)),
+			tuple((alpha_underscore_1::<Span, VerboseError<Span>>, multispace0::<Span, VerboseError<Span>>, char::<Span, VerboseError<Span>>(':'), multispace0::<Span, VerboseError<Span>>, alpha1::<Span, VerboseError<Span>>))
 		),
 		tag(")") // discard
-	)(args)?;
+	)(args).map_err(|f| f.map(ParseError::from))?;
 	// parsing header end
 	// parsing block starts
-	let code_block = code_block.trim();
-	let (remainder, statements): (&str, &str) = delimited(
-		char::<&str, VerboseError<&str>>('{'),
-		take_until_unbalanced('{', '}'), // doesn't support nested {} yet; use parse_hyperlinks::take_until_unbalanced
-		char::<&str, VerboseError<&str>>('}')
-	)(code_block)?;
-	let statements = statements.trim().lines();
-	let statements = statements.into_iter().map(|f| f.trim().to_string()).collect::<Vec<String>>();
+	let code_block = skip_whitespace(code_block);
+	if code_block.fragment().is_empty() {
+		// the body has not been written yet.
+		return Err(Incomplete(Needed::new(1)));
+	}
+	let (remainder, body) = crate::parse_block(code_block).map_err(|err| {
+		if is_unclosed(code_block) {
+			Incomplete(Needed::new(1))
+		} else {
+			nom::Err::Error(err)
+		}
+	})?;
 	// parsing block end
 	Ok((Function {
-			name: name.to_string(),
+			name: name.fragment().to_string(),
 			args: {
-				let args = args;
 				args.into_iter()
 					.map(|f| {
 						FormalArg {
-							identifier: f.0.to_string(),
-							r#type: f.4.to_string(),
+							identifier: f.0.fragment().to_string(),
+							r#type: f.4.fragment().to_string(),
 						}
 					})
 					.collect::<Vec<FormalArg>>()
 			},
-			body: statements,
+			body,
+			exported: is_exported,
 		},
 	remainder))
 }
 
+/// Returns whether the input starts with a `{` which is not closed.
+fn is_unclosed(input: Span) -> bool {
+	if !input.fragment().starts_with('{') {
+		return false;
+	}
+	match take_until_unbalanced('{', '}')(input.slice(1..)) {
+		// the closing brace would be at the start of the remaining input.
+		Ok((remainder, _)) => remainder.fragment().is_empty(),
+		Err(_) => true
+	}
+}
+
 /// Parses a call. The syntax for a call is:
 /// ```sh
 /// COMMAND ARGUMENT* | FUNCTION ARGUMENT*
@@ -104,30 +137,15 @@ pub fn parse_func_declaration(input: &str) -> Result<(Function, &str), nom::Err<
 /// $ echo "string"
 /// ```
 /// makes a call to the `echo` function (defined in the documentation of the [`parse_fn`](self::parse_func_declaration) function).
-pub fn parse_call(input: &str) -> Result<Call, VerboseError<&str>> {
-	match take_while::<_, &str, VerboseError<&str>>(|i: char| !i.is_ascii_whitespace())(input) {
-		Ok(output) => {
-			Ok(Call {
-				name: String::from(output.1),
-				args: {
-					let s = output.0.split_ascii_whitespace();
-					let vec = s.collect::<Vec<&str>>();
-					vec.into_iter().map(|f| f.to_string())
-						.collect::<Vec<String>>()
-				},
-			})
-		}
-		Err(e) => {
-			match e {
-				nom::Err::Incomplete(_) => unsafe {
-					// SAFETY: complete (non-streaming) parsers never return nom::Err::Incomplete.
-					unreachable_unchecked()
-				}
-				nom::Err::Error(e) | nom::Err::Failure(e) => {
-					Err(e)
-				}
-			}
-		}
+///
+/// The call is split into words by the [lexer](crate::lexer), so quoted arguments may contain whitespace.
+/// Arguments keep their quotes; they are removed when the call is executed.
+pub fn parse_call<'a>(input: impl Into<Span<'a>>) -> Result<Call, ParseError> {
+	let input = input.into();
+	let mut words = tokenize(input)?.into_iter().map(|f| f.text.to_string());
+	match words.next() {
+		Some(name) => Ok(Call { name, args: words.collect() }),
+		None => Err(ParseError::new(input, 0, "expected a call"))
 	}
 }
 
@@ -139,15 +157,17 @@ pub struct Call {
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct FormalArg {
-	identifier: String,
-	r#type: String
+	pub identifier: String,
+	pub r#type: String
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Function {
 	pub name: String,
 	pub args: Vec<FormalArg>,
-	pub body: Vec<String>
+	pub body: Block,
+	/// Whether the function was declared with `export`.
+	pub exported: bool
 }
 
 //                   MIT LICENSE:
@@ -176,23 +196,44 @@ pub struct Function {
 // DEALINGS IN THE SOFTWARE.
 //
 // take_until_unbalanced: Copyright (C) the maintainer of `parse_hyperlinks`
-fn take_until_unbalanced(
+pub(crate) fn take_until_unbalanced<'a>(
 	opening_bracket: char,
 	closing_bracket: char,
-) -> impl Fn(&str) -> IResult<&str, &str, VerboseError<&str>> {
-	move |i: &str| {
+) -> impl Fn(Span<'a>) -> IResult<Span<'a>, Span<'a>, VerboseError<Span<'a>>> {
+	move |input: Span<'a>| {
+		let i = *input.fragment();
 		let mut index = 0;
 		let mut bracket_counter = 0;
-		while let Some(n) = &i[index..].find(&[opening_bracket, closing_bracket, '\\'][..]) {
+		while let Some(n) = &i[index..].find(&[opening_bracket, closing_bracket, '\\', '\'', '"'][..]) {
 			index += n;
 			let mut it = i[index..].chars();
 			match it.next().unwrap_or_default() {
-				c if c == '\\' => {
+				'\\' => {
 					// Skip the escape char `\`.
 					index += '\\'.len_utf8();
-					// Skip also the following char.
-					let c = it.next().unwrap_or_default();
-					index += c.len_utf8();
+					// Skip also the following char; if there is none, the bracket is not closed.
+					match it.next() {
+						Some(c) => index += c.len_utf8(),
+						None => break
+					}
+				}
+				quote @ ('\'' | '"') => {
+					// Brackets in quotes do not count. An unclosed quote is skipped by itself,
+					// so that the lexer reports it when the contents are parsed.
+					index += quote.len_utf8();
+					let mut quoted = i[index..].char_indices();
+					while let Some((offset, c)) = quoted.next() {
+						match c {
+							'\\' if quote == '"' => {
+								quoted.next();
+							}
+							c if c == quote => {
+								index += offset + quote.len_utf8();
+								break
+							}
+							_ => {}
+						}
+					}
 				}
 				c if c == opening_bracket => {
 					bracket_counter += 1;
@@ -210,14 +251,14 @@ fn take_until_unbalanced(
 			if bracket_counter == -1 {
 				// We do not consume it.
 				index -= closing_bracket.len_utf8();
-				return Ok((&i[index..], &i[0..index]));
+				return Ok(input.take_split(index));
 			};
 		}
 
 		if bracket_counter == 0 {
-			Ok(("", i))
+			Ok(input.take_split(i.len()))
 		} else {
-			Err(nom::Err::Error(VerboseError::from_error_kind(i, ErrorKind::TakeUntil)))
+			Err(nom::Err::Error(nom::error::ParseError::from_error_kind(input, ErrorKind::TakeUntil)))
 		}
 	}
-}
\ No newline at end of file
+}
@@ -0,0 +1,299 @@
+//! The Deacon lexer.
+//!
+//! The lexer splits a line into [words](TokenKind::Word) and operators, keeping track of the byte span
+//! of every token. Words keep their quotes so that later stages (such as environment variable
+//! substitution) can tell quoted and unquoted text apart; [`word_parts`] and [`unquote`] take a word apart.
+//!
+//! Quoting works as follows:
+//! - Inside single quotes, every character is literal.
+//! - Inside double quotes, a backslash escapes `"` and `\`.
+//! - Outside quotes, a backslash escapes quotes, `\`, whitespace and operator characters.
+//!
+//! Any other backslash is kept as-is, so Windows paths such as `C:\Users` do not need to be escaped.
+//!
+//! A command substitution `$(...)` outside single quotes is part of the word it appears in, even if it contains
+//! whitespace, operators or quotes; [`substitutions`] finds them in a word.
+
+use std::ops::Range;
+use nom::Slice;
+use crate::Span;
+use crate::error::ParseError;
+
+/// A token.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Token<'a> {
+	pub kind: TokenKind,
+	/// The text of this token, exactly as it appears in the input.
+	pub text: &'a str,
+	/// The byte range of this token in the whole input, which is not necessarily
+	/// the input given to [`tokenize`] if that input is a slice of a larger [span](Span).
+	pub span: Range<usize>
+}
+
+/// The kind of a [token](Token).
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TokenKind {
+	/// A word, which may contain quoted strings.
+	Word,
+	/// `|`
+	Pipe,
+	/// `&&`
+	And,
+	/// `||`
+	Or,
+	/// `;`
+	Semicolon,
+	/// `&`, which runs the pipeline before it in the background.
+	Background,
+	/// One of the redirection operators `<`, `>`, `>>`, `2>`, `2>>`, `&>`, `&>>` or `2>&1`.
+	Redirect
+}
+
+/// How a [part of a word](WordPart) is quoted.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Quote {
+	None,
+	Single,
+	Double
+}
+
+/// A part of a word with the same quoting.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct WordPart {
+	/// The text of this part, without quotes and escaping backslashes.
+	pub text: String,
+	pub quote: Quote
+}
+
+/// The operators, in the order they are matched.
+const OPERATORS: [(&str, TokenKind); 13] = [
+	("2>&1", TokenKind::Redirect),
+	("2>>", TokenKind::Redirect),
+	("2>", TokenKind::Redirect),
+	("&>>", TokenKind::Redirect),
+	("&>", TokenKind::Redirect),
+	("&&", TokenKind::And),
+	("||", TokenKind::Or),
+	(">>", TokenKind::Redirect),
+	(">", TokenKind::Redirect),
+	("<", TokenKind::Redirect),
+	("|", TokenKind::Pipe),
+	(";", TokenKind::Semicolon),
+	("&", TokenKind::Background)
+];
+
+/// Splits the input into tokens. Fails if a quote or a `$(` is not closed.
+/// The spans of the tokens are relative to the start of the whole input.
+///
+/// ```
+/// # use deacon_parse::lexer::*;
+/// let tokens = tokenize(r#"echo "a b" 'c'd>out.txt"#).unwrap();
+/// let texts = tokens.iter().map(|f| f.text).collect::<Vec<&str>>();
+/// assert_eq!(texts, vec!["echo", "\"a b\"", "'c'd", ">", "out.txt"]);
+/// assert_eq!(tokens[1].span, 5..10);
+/// assert_eq!(tokens[3].kind, TokenKind::Redirect);
+/// assert_eq!(tokenize("echo \"unclosed").unwrap_err().span, 5..14);
+/// assert_eq!(tokenize("echo $(ls | wc -l)").unwrap()[1].text, "$(ls | wc -l)");
+/// assert_eq!(tokenize("echo $(ls").unwrap_err().span, 5..7);
+/// ```
+pub fn tokenize<'a>(input: impl Into<Span<'a>>) -> Result<Vec<Token<'a>>, ParseError> {
+	let span = input.into();
+	let input = *span.fragment();
+	let base = span.location_offset();
+	let mut tokens = vec![];
+	let mut index = 0;
+	while index < input.len() {
+		let tail = &input[index..];
+		let ch = tail.chars().next().unwrap();
+		if ch.is_whitespace() {
+			index += ch.len_utf8();
+			continue
+		}
+		if let Some((operator, kind)) = OPERATORS.iter().find(|f| tail.starts_with(f.0)) {
+			tokens.push(Token { kind: *kind, text: &tail[..operator.len()], span: base + index..base + index + operator.len() });
+			index += operator.len();
+			continue
+		}
+		let length = word_length(tail).map_err(|(start, message)| {
+			let at = span.slice(index + start..);
+			// an unclosed quote goes on until the end of the input.
+			let length = if message == "unclosed quote" { at.fragment().len() } else { "$(".len() };
+			ParseError::new(at, length, message)
+		})?;
+		tokens.push(Token { kind: TokenKind::Word, text: &tail[..length], span: base + index..base + index + length });
+		index += length;
+	}
+	Ok(tokens)
+}
+
+/// Returns the length of the word at the start of the input, or the index
+/// of the opening quote or `$(` and an error message if it is not closed.
+fn word_length(input: &str) -> Result<usize, (usize, &'static str)> {
+	let mut quote = Quote::None;
+	let mut quote_start = 0;
+	let mut chars = input.char_indices().peekable();
+	while let Some((index, ch)) = chars.next() {
+		match (quote, ch) {
+			(Quote::None, '\\') | (Quote::Double, '\\') if chars.peek().is_some_and(|f| is_escapable(quote, f.1)) => {
+				chars.next();
+			}
+			(Quote::None, '$') | (Quote::Double, '$') if input[index..].starts_with("$(") => {
+				let end = index + substitution_length(&input[index..]).ok_or((index, "unclosed `$(`"))?;
+				while chars.next_if(|f| f.0 < end).is_some() {}
+			}
+			(Quote::None, '\'') => {
+				quote = Quote::Single;
+				quote_start = index;
+			}
+			(Quote::None, '"') => {
+				quote = Quote::Double;
+				quote_start = index;
+			}
+			(Quote::Single, '\'') | (Quote::Double, '"') => quote = Quote::None,
+			(Quote::None, c) if c.is_whitespace() => return Ok(index),
+			// the `2` of `2>` is part of the word unless it starts the word.
+			(Quote::None, _) if OPERATORS.iter().any(|f| !f.0.starts_with('2') && input[index..].starts_with(f.0)) => return Ok(index),
+			_ => {}
+		}
+	}
+	if quote == Quote::None {
+		Ok(input.len())
+	} else {
+		Err((quote_start, "unclosed quote"))
+	}
+}
+
+/// Returns the length of the command substitution at the start of the input, which starts with `$(`,
+/// or [`None`] if it is not closed. Parentheses and substitutions inside it nest, unless they are quoted.
+fn substitution_length(input: &str) -> Option<usize> {
+	let mut quote = Quote::None;
+	let mut depth = 0;
+	let mut index = "$(".len();
+	while let Some(ch) = input[index..].chars().next() {
+		match (quote, ch) {
+			(Quote::None, '\\') | (Quote::Double, '\\') => {
+				index += input[index + 1..].chars().next().filter(|f| is_escapable(quote, *f)).map_or(0, char::len_utf8);
+			}
+			(Quote::None, '$') | (Quote::Double, '$') if input[index..].starts_with("$(") => {
+				index += substitution_length(&input[index..])?;
+				continue
+			}
+			(Quote::None, '\'') => quote = Quote::Single,
+			(Quote::None, '"') => quote = Quote::Double,
+			(Quote::Single, '\'') | (Quote::Double, '"') => quote = Quote::None,
+			(Quote::None, '(') => depth += 1,
+			(Quote::None, ')') if depth == 0 => return Some(index + 1),
+			(Quote::None, ')') => depth -= 1,
+			_ => {}
+		}
+		index += ch.len_utf8();
+	}
+	None
+}
+
+/// Finds the command substitutions `$(...)` of a word, returning the byte range of each one along with
+/// whether it is inside double quotes. Nested substitutions are part of the substitution around them.
+///
+/// ```
+/// # use deacon_parse::lexer::*;
+/// let word = r#"a$(b $(c))"$(d)"'$(e)'"#;
+/// assert_eq!(substitutions(word), vec![(1..10, Quote::None), (11..15, Quote::Double)]);
+/// assert_eq!(&word[1..10], "$(b $(c))");
+/// ```
+pub fn substitutions(word: &str) -> Vec<(Range<usize>, Quote)> {
+	let mut found = vec![];
+	let mut quote = Quote::None;
+	let mut index = 0;
+	while let Some(ch) = word[index..].chars().next() {
+		match (quote, ch) {
+			(Quote::None, '\\') | (Quote::Double, '\\') => {
+				index += word[index + 1..].chars().next().filter(|f| is_escapable(quote, *f)).map_or(0, char::len_utf8);
+			}
+			(Quote::None, '$') | (Quote::Double, '$') if word[index..].starts_with("$(") => {
+				if let Some(length) = substitution_length(&word[index..]) {
+					found.push((index..index + length, quote));
+					index += length;
+					continue
+				}
+			}
+			(Quote::None, '\'') => quote = Quote::Single,
+			(Quote::None, '"') => quote = Quote::Double,
+			(Quote::Single, '\'') | (Quote::Double, '"') => quote = Quote::None,
+			_ => {}
+		}
+		index += ch.len_utf8();
+	}
+	found
+}
+
+/// Quotes the text as a single word, so that [unquoting](unquote) it gives back the text.
+///
+/// ```
+/// # use deacon_parse::lexer::*;
+/// assert_eq!(quote("it's $x"), r#"'it'\''s $x'"#);
+/// assert_eq!(unquote(&quote("it's $x")), "it's $x");
+/// ```
+pub fn quote(text: &str) -> String {
+	format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+fn is_escapable(quote: Quote, ch: char) -> bool {
+	match quote {
+		Quote::None => ch.is_whitespace() || "\"'\\|&;<>".contains(ch),
+		Quote::Double => ch == '"' || ch == '\\',
+		Quote::Single => false
+	}
+}
+
+/// Splits a word into its differently-quoted parts, removing quotes and escaping backslashes.
+///
+/// ```
+/// # use deacon_parse::lexer::*;
+/// assert_eq!(word_parts(r#"a"b c"'d'"#), vec![
+///     WordPart { text: "a".to_string(), quote: Quote::None },
+///     WordPart { text: "b c".to_string(), quote: Quote::Double },
+///     WordPart { text: "d".to_string(), quote: Quote::Single }
+/// ]);
+/// ```
+pub fn word_parts(word: &str) -> Vec<WordPart> {
+	let mut parts = vec![];
+	let mut current = WordPart { text: String::new(), quote: Quote::None };
+	let mut chars = word.chars().peekable();
+	while let Some(ch) = chars.next() {
+		let closes = matches!((current.quote, ch), (Quote::Single, '\'') | (Quote::Double, '"'));
+		let opens = current.quote == Quote::None && (ch == '\'' || ch == '"');
+		if opens || closes {
+			let quote = match ch {
+				_ if closes => Quote::None,
+				'\'' => Quote::Single,
+				_ => Quote::Double
+			};
+			let previous = std::mem::replace(&mut current, WordPart { text: String::new(), quote });
+			// keep empty quoted parts, since `""` is an empty argument.
+			if !previous.text.is_empty() || previous.quote != Quote::None {
+				parts.push(previous);
+			}
+		} else if ch == '\\' && chars.peek().is_some_and(|f| is_escapable(current.quote, *f)) {
+			current.text.extend(chars.next());
+		} else {
+			current.text.push(ch);
+		}
+	}
+	if !current.text.is_empty() {
+		parts.push(current);
+	}
+	parts
+}
+
+/// Removes the quotes and escaping backslashes from a word.
+///
+/// ```
+/// # use deacon_parse::lexer::unquote;
+/// assert_eq!(unquote(r#""my file.txt""#), "my file.txt");
+/// assert_eq!(unquote(r#"C:\Users"#), r#"C:\Users"#);
+/// assert_eq!(unquote(r#"a\ b\"c"#), r#"a b"c"#);
+/// assert_eq!(unquote(r#"'\"'"#), r#"\""#);
+/// ```
+pub fn unquote(word: &str) -> String {
+	word_parts(word).into_iter().map(|f| f.text).collect()
+}
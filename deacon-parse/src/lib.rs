@@ -1,14 +1,206 @@
-#![feature(option_result_contains)]
-
+pub mod ast;
+pub mod command;
+pub mod control;
+pub mod error;
 pub mod function;
+pub mod lexer;
 pub mod types;
 pub mod variable;
 
-use nom::character::complete::char;
-use nom::error::{ErrorKind, ParseError, VerboseError};
-use nom::{AsChar, InputTakeAtPosition, IResult};
+use nom::character::complete::{char, multispace0};
+use nom::error::{ErrorKind, VerboseError};
+use nom::{AsChar, InputTake, InputTakeAtPosition, IResult, Needed, Slice};
 use nom::multi::many1;
-use nom::sequence::delimited;
+use nom::combinator::not;
+use nom::sequence::{delimited, tuple};
+use nom_locate::LocatedSpan;
+use crate::ast::*;
+use crate::command::{error, parse_list, split_tokens};
+use crate::control::{is_keyword, parse_for, parse_if, parse_while};
+use crate::error::ParseError;
+use crate::lexer::{substitutions, tokenize, Token, TokenKind};
+use crate::function::{parse_func_declaration, take_until_unbalanced};
+use crate::variable::{parse_variable_decl, parse_variable_reassignment};
+
+/// A slice of the input which keeps track of its position in the whole input.
+/// Every parser works on spans, so that every [error](ParseError) knows where it happened.
+pub type Span<'a> = LocatedSpan<&'a str>;
+
+/// Parses a Deacon script into its [syntax tree](Script). This is the single entry point
+/// used by the engine, the linter and the REPL.
+///
+/// A script is a list of statements. Function declarations, `if` statements, loops and blocks may span multiple lines;
+/// every other statement spans a single line. Multiple statements on the same line are separated by `;`.
+/// Blocks nest to any depth, and braces in quotes do not count.
+///
+/// ```
+/// # use deacon_parse::parse_script;
+/// # use deacon_parse::ast::*;
+/// let script = parse_script("func greet() {\n    echo hi\n}\ngreet").unwrap();
+/// assert_eq!(script.statements.len(), 2);
+/// assert!(matches!(script.statements[0], Statement::FunctionDecl(_)));
+/// assert!(matches!(script.statements[1], Statement::Expr(Expr::Command(_))));
+///
+/// let err = parse_script("echo hi\nlet $x =").unwrap_err();
+/// assert_eq!((err.line, err.column), (2, 9));
+/// ```
+pub fn parse_script<'a>(input: impl Into<Span<'a>>) -> Result<Script, ParseError> {
+	let mut input = skip_whitespace(input.into());
+	let mut statements = vec![];
+	while !input.fragment().is_empty() {
+		if input.fragment().starts_with('{') {
+			let (remainder, block) = parse_block(input)?;
+			statements.push(Statement::Block(block));
+			input = skip_whitespace(remainder);
+			continue
+		}
+		if is_keyword(input.fragment(), "if") {
+			let (remainder, r#if) = parse_if(input)?;
+			statements.push(Statement::If(r#if));
+			input = skip_whitespace(remainder);
+			continue
+		}
+		if is_keyword(input.fragment(), "while") {
+			let (remainder, r#while) = parse_while(input)?;
+			statements.push(Statement::While(r#while));
+			input = skip_whitespace(remainder);
+			continue
+		}
+		if is_keyword(input.fragment(), "for") {
+			let (remainder, r#for) = parse_for(input)?;
+			statements.push(Statement::For(r#for));
+			input = skip_whitespace(remainder);
+			continue
+		}
+		if is_keyword(input.fragment(), "else") {
+			return Err(ParseError::new(input, "else".len(), "`else` without `if`"));
+		}
+		match parse_func_declaration(input) {
+			Ok((func, remainder)) => {
+				statements.push(Statement::FunctionDecl(func));
+				input = skip_whitespace(remainder);
+			}
+			Err(nom::Err::Incomplete(Needed::Unknown)) => {
+				// we now know it's a statement and not a function.
+				let (remainder, line) = input.take_split(input.fragment().find('\n').unwrap_or(input.fragment().len()));
+				let tokens = tokenize(line)?;
+				check_substitutions(line, &tokens)?;
+				let parts = split_tokens(&tokens, |f| f == TokenKind::Semicolon || f == TokenKind::Background);
+				let mut next = skip_whitespace(remainder);
+				for (index, (statement, _)) in parts.iter().enumerate() {
+					// a statement is terminated by the separator of the next part.
+					let background = parts.get(index + 1).and_then(|f| f.1).filter(|f| f.kind == TokenKind::Background);
+					let (Some(first), Some(last)) = (statement.first(), statement.last()) else {
+						if background.is_some() {
+							return Err(error(line, background, "expected a pipeline before `&`"));
+						}
+						continue
+					};
+					let base = line.location_offset();
+					let start = input.slice(first.span.start - input.location_offset()..);
+					if start.location_offset() != input.location_offset() && starts_block(start.fragment()) {
+						// the block may continue on the next lines, so the rest of the input is parsed from there.
+						next = start;
+						break
+					}
+					let statement = parse_statement(line.slice(first.span.start - base..last.span.end - base))?;
+					statements.push(match (statement, background) {
+						(statement, None) => statement,
+						(Statement::Expr(Expr::Command(command)), Some(_)) => Statement::Expr(Expr::Background(Pipeline { commands: vec![command] })),
+						(Statement::Expr(Expr::Pipeline(pipeline)), Some(_)) => Statement::Expr(Expr::Background(pipeline)),
+						(_, background) => return Err(error(line, background, "only a pipeline can run in the background"))
+					});
+				}
+				input = next;
+			}
+			Err(nom::Err::Incomplete(_)) => {
+				// the function's body is missing or not closed.
+				return Err(match input.fragment().find('{') {
+					Some(brace) => ParseError::new(input.slice(brace..), 1, "unclosed `{`"),
+					None => ParseError::new(input.slice(input.fragment().len()..), 0, "expected the body of the function")
+				});
+			}
+			Err(nom::Err::Error(err) | nom::Err::Failure(err)) => return Err(err)
+		}
+	}
+	Ok(Script { statements })
+}
+
+/// Parses a single-line statement; that is, anything but a function declaration, an `if` statement, a loop or a block.
+///
+/// ```
+/// # use deacon_parse::parse_statement;
+/// # use deacon_parse::ast::*;
+/// assert!(matches!(parse_statement("let $x = 1"), Ok(Statement::VariableDecl(_))));
+/// assert!(matches!(parse_statement("$x = 2"), Ok(Statement::VariableReassignment(_))));
+/// assert!(matches!(parse_statement("$(which ls) -la"), Ok(Statement::Expr(Expr::Command(_)))));
+/// assert!(matches!(parse_statement("echo $x"), Ok(Statement::Expr(Expr::Command(_)))));
+/// assert!(matches!(parse_statement("echo $x | grep y"), Ok(Statement::Expr(Expr::Pipeline(_)))));
+/// assert!(matches!(parse_statement("cargo build && cargo run"), Ok(Statement::Expr(Expr::And(_, _)))));
+/// assert_eq!(parse_statement("break"), Ok(Statement::Break));
+/// assert!(parse_statement("let x = 1").is_err());
+/// assert!(parse_statement("continue 2").is_err());
+/// ```
+pub fn parse_statement<'a>(input: impl Into<Span<'a>>) -> Result<Statement, ParseError> {
+	let input = skip_whitespace(input.into());
+	let input = input.slice(..input.fragment().trim_end().len());
+	let fragment = *input.fragment();
+	for (keyword, statement) in [("break", Statement::Break), ("continue", Statement::Continue)] {
+		if is_keyword(fragment, keyword) {
+			let extra = skip_whitespace(input.slice(keyword.len()..));
+			if !extra.fragment().is_empty() {
+				return Err(ParseError::new(extra, extra.fragment().len(), format!("`{}` takes no arguments", keyword)));
+			}
+			return Ok(statement);
+		}
+	}
+	if fragment.strip_prefix("let").is_some_and(|f| f.starts_with(|c: char| c.is_ascii_whitespace())) {
+		parse_variable_decl(input).map(Statement::VariableDecl)
+	} else if is_reassignment(input) {
+		parse_variable_reassignment(input).map(Statement::VariableReassignment)
+	} else {
+		parse_list(input).map(Statement::Expr)
+	}
+}
+
+/// Returns whether the input starts like a reassignment, `$name =`, rather than like a command
+/// whose name is expanded, such as `$(which ls) -la`.
+fn is_reassignment(input: Span) -> bool {
+	tuple((char::<Span, ()>('$'), alpha_underscore_1, multispace0, char('='), not(char('='))))(input).is_ok()
+}
+
+/// Parses a block of statements delimited by `{` and `}`, returning the remaining input.
+/// Blocks may be nested.
+pub fn parse_block(input: Span) -> Result<(Span, Block), ParseError> {
+	let (remainder, statements) = delimited(
+		char::<Span, VerboseError<Span>>('{'),
+		take_until_unbalanced('{', '}'),
+		char::<Span, VerboseError<Span>>('}')
+	)(input).map_err(|_| ParseError::new(input, 1, "unclosed `{`"))?;
+	Ok((remainder, Block { statements: parse_script(statements)?.statements }))
+}
+
+/// Returns whether the input starts with a statement which may span multiple lines, such as a block or a loop.
+fn starts_block(input: &str) -> bool {
+	input.starts_with('{') || ["if", "else", "while", "for", "func", "export"].iter().any(|f| is_keyword(input, f))
+}
+
+/// Parses the script inside every command substitution `$(...)` of the tokens of the given input,
+/// so that its errors are found along with the errors of the input itself.
+pub(crate) fn check_substitutions(input: Span, tokens: &[Token]) -> Result<(), ParseError> {
+	for token in tokens.iter().filter(|f| f.kind == TokenKind::Word) {
+		let start = token.span.start - input.location_offset();
+		for (range, _) in substitutions(token.text) {
+			parse_script(input.slice(start + range.start + "$(".len()..start + range.end - 1))?;
+		}
+	}
+	Ok(())
+}
+
+/// Skips any whitespace (including newlines) at the start of the input.
+pub(crate) fn skip_whitespace(input: Span) -> Span {
+	multispace0::<Span, ()>(input).map_or(input, |f| f.0)
+}
 
 /// Parses environment variables. If there is no environmental variable to substitute, this function will return [`None`].
 /// Environment variables are delimited in `?`.
@@ -42,27 +234,20 @@ pub fn parse_env_vars(input: &str) -> Option<Vec<&str>> {
 	}
 }
 
-pub(crate) fn alpha_underscore_0<T: InputTakeAtPosition, E: ParseError<T>>(input: T) -> IResult<T, T, E> where <T as InputTakeAtPosition>::Item: AsChar {
+pub(crate) fn alpha_underscore_0<T: InputTakeAtPosition, E: nom::error::ParseError<T>>(input: T) -> IResult<T, T, E> where <T as InputTakeAtPosition>::Item: AsChar {
 	input.split_at_position_complete(|item| !({
 		let ch = item.as_char();
 		ch.is_alpha() || ch == '_'
 	}))
 }
 
-pub(crate) fn alpha_underscore_1<T: InputTakeAtPosition, E: ParseError<T>>(input: T) -> IResult<T, T, E> where <T as InputTakeAtPosition>::Item: AsChar {
+pub(crate) fn alpha_underscore_1<T: InputTakeAtPosition, E: nom::error::ParseError<T>>(input: T) -> IResult<T, T, E> where <T as InputTakeAtPosition>::Item: AsChar {
 	input.split_at_position1_complete(|item| !({
 		let ch = item.as_char();
 		ch.is_alpha() || ch == '_'
 	}), ErrorKind::Alpha)
 }
 
-pub(crate) static MAP_ERR: fn(nom::Err<VerboseError<&str>>) -> VerboseError<&str> = |f| {
-	match f {
-		nom::Err::Incomplete(_) => unsafe { std::hint::unreachable_unchecked() },
-		nom::Err::Error(e) | nom::Err::Failure(e) => e
-	}
-};
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -119,6 +304,247 @@ mod tests {
 		assert_eq!(parse_func_declaration("func f() {\necho $s\n}").unwrap().0.name, "f");
 	}
 
+	#[test]
+	fn parse_scripts() {
+		use ast::*;
+		let script = parse_script("func x(s: string) {\n    echo $s\n    let $y = 1\n}\n\nx hello\n{\n    echo nested\n}").unwrap();
+		assert_eq!(script.statements.len(), 3);
+		let func = script.functions().next().unwrap();
+		assert_eq!(func.name, "x");
+		assert!(!func.exported);
+		assert_eq!(func.args, vec![FormalArg { identifier: "s".to_string(), r#type: "string".to_string() }]);
+		assert_eq!(func.body.statements, vec![
+			Statement::Expr(Expr::Command(Command { call: Call { name: "echo".to_string(), args: vec!["$s".to_string()] }, redirects: vec![] })),
+			Statement::VariableDecl(Variable { identifier: "y".to_string(), value: "1".to_string() })
+		]);
+		assert_eq!(script.statements[1], Statement::Expr(Expr::Command(Command { call: Call { name: "x".to_string(), args: vec!["hello".to_string()] }, redirects: vec![] })));
+		assert!(matches!(&script.statements[2], Statement::Block(block) if block.statements.len() == 1));
+		assert!(parse_script("export func y() {\n}").unwrap().functions().next().unwrap().exported);
+		// `export` is a word of its own.
+		assert!(matches!(parse_func_declaration("exportfunc y() {\n}"), Err(nom::Err::Incomplete(Needed::Unknown))));
+		assert_eq!(parse_script("exportfunc y() {\n}").unwrap().functions().count(), 0);
+		assert!(parse_script("func z() {\n    func inner() {\n    }\n}").is_ok());
+		assert!(parse_script("let x = 1").is_err());
+		assert_eq!(parse_script("\n\n").unwrap(), Script::default());
+	}
+
+	#[test]
+	fn parse_error_locations() {
+		let err = parse_script("echo hi\necho \"unclosed").unwrap_err();
+		assert_eq!((err.line, err.column, err.span), (2, 6, 13..22));
+		assert_eq!(err.message, "unclosed quote");
+		let err = parse_script("func x() {\n    echo hi\n").unwrap_err();
+		assert_eq!((err.line, err.column, err.span), (1, 10, 9..10));
+		let err = parse_script("func x() {\n    ls | \n}").unwrap_err();
+		assert_eq!((err.line, err.column, err.span), (2, 8, 18..19));
+		let err = parse_script("func x()").unwrap_err();
+		assert_eq!((err.line, err.column, err.span), (1, 9, 8..8));
+		let err = parse_script("func 1() {\n}").unwrap_err();
+		assert_eq!((err.line, err.column), (1, 6));
+		let err = parse_script("ls; let $x").unwrap_err();
+		assert_eq!((err.line, err.column), (1, 11));
+	}
+
+	#[test]
+	fn parse_pipelines() {
+		use ast::*;
+		use command::parse_pipeline;
+		assert_eq!(
+			parse_pipeline("ls | grep \"a | b\" | wc -l"),
+			Ok(Expr::Pipeline(Pipeline {
+				commands: vec![
+					Command { call: Call { name: "ls".to_string(), args: vec![] }, redirects: vec![] },
+					Command { call: Call { name: "grep".to_string(), args: vec!["\"a | b\"".to_string()] }, redirects: vec![] },
+					Command { call: Call { name: "wc".to_string(), args: vec!["-l".to_string()] }, redirects: vec![] }
+				]
+			}))
+		);
+		assert!(parse_pipeline("| ls").is_err());
+		assert!(parse_pipeline("ls | | wc").is_err());
+	}
+
+	#[test]
+	fn parse_lists() {
+		use ast::*;
+		let command = |name: &str| Box::new(Expr::Command(Command { call: Call { name: name.to_string(), args: vec![] }, redirects: vec![] }));
+		assert_eq!(
+			parse_script("a && b || c; d").unwrap().statements,
+			vec![
+				Statement::Expr(Expr::Or(Box::new(Expr::And(command("a"), command("b"))), command("c"))),
+				Statement::Expr(*command("d"))
+			]
+		);
+		assert_eq!(parse_script("a; ").unwrap().statements, vec![Statement::Expr(*command("a"))]);
+		assert_eq!(parse_script("echo \"a && b; c\"").unwrap().statements.len(), 1);
+		assert!(parse_script("|| a").is_err());
+		assert!(parse_script("a && && b").is_err());
+	}
+
+	#[test]
+	fn parse_background() {
+		use ast::*;
+		let script = parse_script("sleep 1 & ls | wc -l &\necho done").unwrap();
+		assert!(matches!(&script.statements[0], Statement::Expr(Expr::Background(p)) if p.commands.len() == 1));
+		assert!(matches!(&script.statements[1], Statement::Expr(Expr::Background(p)) if p.commands.len() == 2));
+		assert!(matches!(&script.statements[2], Statement::Expr(Expr::Command(_))));
+		assert!(matches!(parse_script("echo a&b").unwrap().statements[..], [_, _]));
+		let err = parse_script("a && b &").unwrap_err();
+		assert_eq!((err.span, err.message.as_str()), (7..8, "only a pipeline can run in the background"));
+		assert_eq!(parse_script("ls; &").unwrap_err().span, 4..5);
+		assert!(parse_script("let $x = 1 &").is_err());
+	}
+
+	#[test]
+	fn parse_ifs() {
+		use ast::*;
+		let command = |name: &str| Statement::Expr(Expr::Command(Command { call: Call { name: name.to_string(), args: vec![] }, redirects: vec![] }));
+		let script = parse_script("func f() {\n    if not a {\n        b\n    }\n    else if $x == y {\n        c\n    } else {\n        d\n    }\n}").unwrap();
+		let func = script.functions().next().unwrap();
+		assert_eq!(func.body.statements, vec![Statement::If(If {
+			branches: vec![
+				(Condition::Expr(Expr::Command(Command { call: Call { name: "not".to_string(), args: vec!["a".to_string()] }, redirects: vec![] })), Block { statements: vec![command("b")] }),
+				(Condition::Compare { left: "$x".to_string(), right: "y".to_string(), equal: true }, Block { statements: vec![command("c")] })
+			],
+			otherwise: Some(Block { statements: vec![command("d")] })
+		})]);
+		assert_eq!(parse_script("if true { a }; b").unwrap().statements.len(), 2);
+		let err = parse_script("ls\nif true\n    a\n").unwrap_err();
+		assert_eq!((err.line, err.column, err.message.as_str()), (2, 8, "expected `{` after the condition"));
+		let err = parse_script("if {\n}").unwrap_err();
+		assert_eq!((err.line, err.column, err.message.as_str()), (1, 4, "expected a condition"));
+		let err = parse_script("if a {\n} else b").unwrap_err();
+		assert_eq!((err.line, err.column), (2, 3));
+		let err = parse_script("if a {\n    b").unwrap_err();
+		assert_eq!((err.span, err.message.as_str()), (5..6, "unclosed `{`"));
+		assert_eq!(parse_script("else {\n}").unwrap_err().span, 0..4);
+		assert!(parse_script("if a | {\n}").is_err());
+	}
+
+	#[test]
+	fn parse_loops() {
+		use ast::*;
+		let script = parse_script("for $x in a \"b c\" {\n    while $x != b {\n        if true { break }\n        continue\n    }\n}").unwrap();
+		let Statement::For(r#for) = &script.statements[0] else { panic!("expected a for loop") };
+		assert_eq!((r#for.variable.as_str(), &r#for.items), ("x", &Items::Words(vec!["a".to_string(), "\"b c\"".to_string()])));
+		let Statement::While(r#while) = &r#for.body.statements[0] else { panic!("expected a while loop") };
+		assert_eq!(r#while.condition, Condition::Compare { left: "$x".to_string(), right: "b".to_string(), equal: false });
+		assert!(matches!(&r#while.body.statements[..], [Statement::If(r#if), Statement::Continue] if r#if.branches[0].1.statements == vec![Statement::Break]));
+		let script = parse_script("for $f in $(ls $(pwd)) {\n}").unwrap();
+		assert!(matches!(&script.statements[0], Statement::For(For { items: Items::Output(Expr::Command(command)), .. }) if command.call.args == vec!["$(pwd)".to_string()]));
+		let err = parse_script("while true {\n    for $x in a b {\n        echo $x\n    }\n").unwrap_err();
+		assert_eq!((err.span, err.message.as_str()), (11..12, "unclosed `{`"));
+		let err = parse_script("for x in a {\n}").unwrap_err();
+		assert_eq!((err.span, err.message.as_str()), (4..5, "expected a variable such as `$x` after `for`"));
+		let err = parse_script("for $x of a {\n}").unwrap_err();
+		assert_eq!(err.span, 7..11);
+		let err = parse_script("for $x in $(ls {\n}").unwrap_err();
+		assert_eq!((err.span, err.message.as_str()), (10..12, "unclosed `$(`"));
+		let err = parse_script("for $x in a | b {\n}").unwrap_err();
+		assert_eq!(err.span, 12..13);
+		let err = parse_script("while true {\n    break 2\n}").unwrap_err();
+		assert_eq!((err.line, err.column), (2, 11));
+	}
+
+	#[test]
+	fn parse_nested_blocks() {
+		use ast::*;
+		let command = |name: &str, args: &[&str]| Statement::Expr(Expr::Command(Command {
+			call: Call { name: name.to_string(), args: args.iter().map(|f| f.to_string()).collect() },
+			redirects: vec![]
+		}));
+		let script = parse_script("func f() {\n    if true {\n        for $x in a {\n            while false { echo \"}\"; if true { echo '{' } }\n        }\n    }\n}").unwrap();
+		let Statement::If(r#if) = &script.functions().next().unwrap().body.statements[0] else { panic!("expected an if") };
+		let Statement::For(r#for) = &r#if.branches[0].1.statements[0] else { panic!("expected a for loop") };
+		let Statement::While(r#while) = &r#for.body.statements[0] else { panic!("expected a while loop") };
+		assert_eq!(r#while.body.statements, vec![
+			command("echo", &["\"}\""]),
+			Statement::If(If { branches: vec![(Condition::Bool(true), Block { statements: vec![command("echo", &["'{'"])] })], otherwise: None })
+		]);
+		assert_eq!(parse_script("{ a; { b }; c }").unwrap().statements, vec![Statement::Block(Block {
+			statements: vec![command("a", &[]), Statement::Block(Block { statements: vec![command("b", &[])] }), command("c", &[])]
+		})]);
+		assert!(matches!(&parse_script("ls; func f() {\n}").unwrap().statements[..], [_, Statement::FunctionDecl(_)]));
+		assert_eq!(parse_script("export x; y").unwrap().statements, vec![command("export", &["x"]), command("y", &[])]);
+		let err = parse_script("func f() {\n    if true {\n        for $x in a {\n            ls |\n        }\n    }\n}").unwrap_err();
+		assert_eq!((err.line, err.column), (4, 16));
+		let err = parse_script("func f() {\n    if true {\n        echo \"}\n    }\n}").unwrap_err();
+		assert_eq!((err.line, err.column, err.message.as_str()), (3, 14, "unclosed quote"));
+		let err = parse_script("a; if true {\n    b\n").unwrap_err();
+		assert_eq!((err.span, err.message.as_str()), (11..12, "unclosed `{`"));
+		assert_eq!(parse_script("a; else {\n}").unwrap_err().span, 3..7);
+		// a trailing backslash escapes nothing, so the block is still open.
+		for (input, brace) in [("{ echo \\", 0..1), ("if true { echo \\", 8..9), ("func x() { echo \\", 9..10)] {
+			let err = parse_script(input).unwrap_err();
+			assert_eq!((err.span, err.message.as_str()), (brace, "unclosed `{`"), "{}", input);
+		}
+	}
+
+	#[test]
+	fn tokenize_input() {
+		use lexer::*;
+		let kinds = |input| tokenize(input).unwrap().into_iter().map(|f| (f.kind, f.text)).collect::<Vec<(TokenKind, &str)>>();
+		assert_eq!(kinds("a|b&&c||d;e"), vec![
+			(TokenKind::Word, "a"), (TokenKind::Pipe, "|"), (TokenKind::Word, "b"), (TokenKind::And, "&&"),
+			(TokenKind::Word, "c"), (TokenKind::Or, "||"), (TokenKind::Word, "d"), (TokenKind::Semicolon, ";"), (TokenKind::Word, "e")
+		]);
+		assert_eq!(kinds(r#"cd "C:\Program Files" a\ b 'it''s'"#), vec![
+			(TokenKind::Word, "cd"), (TokenKind::Word, r#""C:\Program Files""#), (TokenKind::Word, r#"a\ b"#), (TokenKind::Word, "'it''s'")
+		]);
+		assert_eq!(kinds("echo 2>&1 a2>b"), vec![
+			(TokenKind::Word, "echo"), (TokenKind::Redirect, "2>&1"), (TokenKind::Word, "a2"), (TokenKind::Redirect, ">"), (TokenKind::Word, "b")
+		]);
+		assert_eq!(tokenize("  ls  -la").unwrap()[1].span, 6..9);
+		assert_eq!(unquote(r#""C:\Program Files""#), r#"C:\Program Files"#);
+		assert_eq!(unquote(r#"\|\;"#), "|;");
+		assert_eq!(unquote("''"), "");
+		assert!(tokenize("echo 'unclosed").is_err());
+	}
+
+	#[test]
+	fn parse_substitutions() {
+		use ast::*;
+		use control::parse_condition;
+		use lexer::*;
+		let script = parse_script("let $branch = $(git rev-parse --abbrev-ref HEAD); echo \"on $(echo \")\") in $(basename $(pwd))\"").unwrap();
+		assert_eq!(script.statements[0], Statement::VariableDecl(Variable {
+			identifier: "branch".to_string(),
+			value: "$(git rev-parse --abbrev-ref HEAD)".to_string()
+		}));
+		let Statement::Expr(Expr::Command(command)) = &script.statements[1] else { panic!("expected a command") };
+		let word = &command.call.args[0];
+		assert_eq!(substitutions(word), vec![(4..15, Quote::Double), (19..37, Quote::Double)]);
+		assert_eq!(&word[4..15], "$(echo \")\")");
+		assert_eq!(substitutions("'$(b)'"), vec![]);
+		assert!(matches!(parse_condition("$(id -u) == 0"), Ok(Condition::Compare { left, .. }) if left == "$(id -u)"));
+		let err = parse_script("echo hi\necho $(ls | && b)").unwrap_err();
+		assert_eq!((err.line, err.column, err.message.as_str()), (2, 11, "expected a call on both sides of `|`"));
+		let err = parse_script("if $(echo (a) {\n}").unwrap_err();
+		assert_eq!((err.span, err.message.as_str()), (3..5, "unclosed `$(`"));
+		let err = parse_script("for $x in a $(for) {\n}").unwrap_err();
+		assert_eq!(err.message, "expected `{` after the items");
+	}
+
+	#[test]
+	fn parse_redirections() {
+		use ast::*;
+		use command::parse_command;
+		let command = parse_command("cat < in.txt > out.txt 2>> err.txt").unwrap();
+		assert_eq!(command.call, Call { name: "cat".to_string(), args: vec![] });
+		assert_eq!(command.redirects, vec![
+			Redirect::Stdin("in.txt".to_string()),
+			Redirect::Stdout { path: "out.txt".to_string(), append: false },
+			Redirect::Stderr { path: "err.txt".to_string(), append: true }
+		]);
+		let command = parse_command("cargo build &>build.log").unwrap();
+		assert_eq!(command.call.args, vec!["build".to_string()]);
+		assert_eq!(command.redirects, vec![Redirect::Both { path: "build.log".to_string(), append: false }]);
+		assert_eq!(parse_command("echo a2>b").unwrap().redirects, vec![Redirect::Stdout { path: "b".to_string(), append: false }]);
+		assert_eq!(parse_command("echo hi > C:\\out.txt").unwrap().redirects, vec![Redirect::Stdout { path: "C:\\out.txt".to_string(), append: false }]);
+		assert!(parse_command("echo '2>&1'").unwrap().redirects.is_empty());
+		assert_eq!(parse_command("echo hi > ~/\"$f.txt\"").unwrap().redirects, vec![Redirect::Stdout { path: "~/\"$f.txt\"".to_string(), append: false }]);
+		assert!(parse_command("> out.txt").is_err());
+	}
+
 	#[test]
 	fn parse_types() {
 	    use types::DeaconType::*;
@@ -138,4 +564,20 @@ mod tests {
 		assert_eq!(parse_variable_decl("let $abc = \"def\"").ok(), Some(Variable { identifier: "abc".to_string(), value: "\"def\"".to_string() }));
 	}
 
+	#[test]
+	fn parse_variable_reassignments() {
+		assert_eq!(parse_variable_reassignment("$var = hello").ok(), Some(Variable { identifier: "var".to_string(), value: "hello".to_string() }));
+		assert_eq!(parse_variable_reassignment("$abc=\"d e\"").ok(), Some(Variable { identifier: "abc".to_string(), value: "\"d e\"".to_string() }));
+		assert_eq!(parse_variable_reassignment("var = 1").ok(), None);
+		assert_eq!(parse_variable_reassignment("$var =").ok(), None);
+		// a command whose name comes from a substitution or a variable is not a reassignment.
+		let call = |name: &str, args: &[&str]| Statement::Expr(ast::Expr::Command(ast::Command {
+			call: Call { name: name.to_string(), args: args.iter().map(|f| f.to_string()).collect() },
+			redirects: vec![]
+		}));
+		assert_eq!(parse_script("$(which ls) -la\n$editor file").unwrap().statements, vec![call("$(which ls)", &["-la"]), call("$editor", &["file"])]);
+		assert!(matches!(parse_statement("$x=1"), Ok(Statement::VariableReassignment(_))));
+		assert!(parse_statement("$x =").is_err());
+	}
+
 }
\ No newline at end of file
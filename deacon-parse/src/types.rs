@@ -24,7 +24,7 @@ pub enum DeaconType {
 impl<'a> TryFrom<&'a str> for DeaconType {
 	type Error = VerboseError<&'a str>;
 	fn try_from(value: &'a str) -> Result<DeaconType, Self::Error> {
-		match value.as_ref() {
+		match value {
 			"string" => Ok(DeaconType::String),
 			"int" => Ok(DeaconType::Int),
 			"bool" => Ok(DeaconType::Bool),
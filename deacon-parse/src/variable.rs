@@ -1,10 +1,10 @@
 //! Standard variable parsing.
 use nom::bytes::complete::{tag, take_while1};
-use nom::character::complete::{char, multispace1};
-use nom::character::streaming::multispace0;
+use nom::character::complete::{char, multispace0, multispace1};
 use nom::error::VerboseError;
 use nom::sequence::tuple;
-use crate::alpha_underscore_1;
+use crate::{alpha_underscore_1, Span};
+use crate::error::ParseError;
 
 /// Parse a variable declaration in the form:
 /// ```sh
@@ -14,22 +14,21 @@ use crate::alpha_underscore_1;
 /// current scope, the value of that previous variable is freed to save memory. To modify a
 /// variable instead, one can [reassign](self::parse_variable_reassignment) the variable
 /// to another value instead.
-pub fn parse_variable_decl(input: &str) -> Result<Variable, VerboseError<&str>> {
+pub fn parse_variable_decl<'a>(input: impl Into<Span<'a>>) -> Result<Variable, ParseError> {
 	//..................... .let..........$    idf..........=..........val.//
-	let res: Result<(&str, (&str, &str, char, &str, &str, char, &str, &str)), VerboseError<&str>> = tuple((
-		tag::<_, &str, VerboseError<&str>>("let"),
-		multispace1::<&str, VerboseError<&str>>,
-		char::<&str, VerboseError<&str>>('$'),
-		alpha_underscore_1::<&str, VerboseError<&str>>,
-		multispace0::<&str, VerboseError<&str>>,
-		char::<&str, VerboseError<&str>>('='),
-		multispace0::<&str, VerboseError<&str>>,
-		take_while1::<_, &str, VerboseError<&str>>(|ch| ch != '\n')
-	))(input).map_err(crate::MAP_ERR);
-	let var = res?.1.to_owned();
+	let (_, (_, _, _, identifier, _, _, _, value)) = tuple((
+		tag::<_, Span, VerboseError<Span>>("let"),
+		multispace1::<Span, VerboseError<Span>>,
+		char::<Span, VerboseError<Span>>('$'),
+		alpha_underscore_1::<Span, VerboseError<Span>>,
+		multispace0::<Span, VerboseError<Span>>,
+		char::<Span, VerboseError<Span>>('='),
+		multispace0::<Span, VerboseError<Span>>,
+		take_while1::<_, Span, VerboseError<Span>>(|ch| ch != '\n')
+	))(input.into()).map_err(ParseError::from)?;
 	Ok(Variable {
-		identifier: var.3.trim().to_string(),
-		value: var.7.trim().to_string()
+		identifier: identifier.fragment().trim().to_string(),
+		value: value.fragment().trim().to_string()
 	})
 }
 
@@ -38,21 +37,21 @@ pub fn parse_variable_decl(input: &str) -> Result<Variable, VerboseError<&str>>
 /// WHITESPACE '$' IDENTIFIER '=' value
 /// ```
 /// Note that this totally differs from [variable declaration](self::parse_variable_decl), which frees old variables
-/// of the same name in the same scope.
-pub fn parse_variable_reassignment(input: &str) -> Result<Variable, VerboseError<&str>> {
+/// of the same name in the same scope. The variable must already be declared, either in the current scope or in
+/// a scope around it, and the innermost such variable is the one modified.
+pub fn parse_variable_reassignment<'a>(input: impl Into<Span<'a>>) -> Result<Variable, ParseError> {
 	//........................$    idf..........=..........val.//
-	let res: Result<(&str, (char, &str, &str, char, &str, &str)), VerboseError<&str>> = tuple((
-		char::<&str, VerboseError<&str>>('$'),
-		alpha_underscore_1::<&str, VerboseError<&str>>,
-		multispace0::<&str, VerboseError<&str>>,
-		char::<&str, VerboseError<&str>>('='),
-		multispace0::<&str, VerboseError<&str>>,
-		take_while1::<_, &str, VerboseError<&str>>(|ch| ch != '\n')
-	))(input).map_err(crate::MAP_ERR);
-	let var = res?.1.to_owned();
+	let (_, (_, identifier, _, _, _, value)) = tuple((
+		char::<Span, VerboseError<Span>>('$'),
+		alpha_underscore_1::<Span, VerboseError<Span>>,
+		multispace0::<Span, VerboseError<Span>>,
+		char::<Span, VerboseError<Span>>('='),
+		multispace0::<Span, VerboseError<Span>>,
+		take_while1::<_, Span, VerboseError<Span>>(|ch| ch != '\n')
+	))(input.into()).map_err(ParseError::from)?;
 	Ok(Variable {
-		identifier: var.2.trim().to_string(),
-		value: var.5.trim().to_string()
+		identifier: identifier.fragment().trim().to_string(),
+		value: value.fragment().trim().to_string()
 	})
 }
 
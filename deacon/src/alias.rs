@@ -0,0 +1,79 @@
+//! Command aliases.
+//!
+//! The aliases of a session start out as the `command_aliases` of the [configuration](crate::config::CONFIG),
+//! and are edited with the `alias` and `unalias` builtins. Aliases are expanded before builtins and
+//! processes are resolved, so an alias can refer to either. Prefixing a name with `!` bypasses its alias.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashSet};
+use deacon_config::DeaconConfig;
+use deacon_parse::lexer::{tokenize, TokenKind};
+use crate::config::CONFIG;
+
+thread_local! {
+	static ALIASES: RefCell<BTreeMap<String, String>> = RefCell::new(CONFIG.command_aliases.clone().into_iter().collect());
+}
+
+/// Returns the words of an alias's value, or an error message if the value is not a single command.
+fn alias_words(value: &str) -> Result<Vec<String>, &'static str> {
+	match tokenize(value) {
+		Ok(tokens) if tokens.is_empty() => Err("an alias cannot be empty"),
+		Ok(tokens) if tokens.iter().all(|f| f.kind == TokenKind::Word) => Ok(tokens.iter().map(|f| f.text.to_string()).collect()),
+		Ok(_) => Err("an alias must be a single command, without operators"),
+		Err(_) => Err("an alias cannot have unclosed quotes")
+	}
+}
+
+/// Expands the alias of the first word, if it has one. The first word of the expansion is expanded
+/// again, unless it is an alias which has already been expanded, so that aliases such as `ls = "ls -la"`
+/// or aliases that refer to each other do not recurse forever.
+pub fn expand_aliases(mut words: Vec<String>) -> Vec<String> {
+	let mut expanded = HashSet::new();
+	while let Some(value) = words.first().and_then(|f| get_alias(f)) {
+		if !expanded.insert(words[0].clone()) {
+			break
+		}
+		match alias_words(&value) {
+			Ok(alias) => {
+				words.splice(..1, alias);
+			}
+			Err(err) => {
+				eprintln!("{}", ansi_term::Colour::Red.paint(format!("The alias \"{}\" is ignored: {}.", words[0], err)));
+				break
+			}
+		}
+	}
+	words
+}
+
+/// Returns the value of the given alias.
+pub fn get_alias(name: &str) -> Option<String> {
+	ALIASES.with(|f| f.borrow().get(name).cloned())
+}
+
+/// Returns every alias, sorted by name.
+pub fn aliases() -> Vec<(String, String)> {
+	ALIASES.with(|f| f.borrow().clone().into_iter().collect())
+}
+
+/// Defines an alias for this session, replacing any previous alias of the same name.
+pub fn set_alias(name: &str, value: &str) -> Result<(), String> {
+	if name.is_empty() || name.starts_with('!') || name.contains(|c: char| c.is_whitespace() || c == '=') {
+		return Err(format!("\"{}\" is not a valid alias name", name));
+	}
+	alias_words(value)?;
+	ALIASES.with(|f| f.borrow_mut().insert(name.to_string(), value.to_string()));
+	Ok(())
+}
+
+/// Removes an alias from this session, returning whether it existed.
+pub fn remove_alias(name: &str) -> bool {
+	ALIASES.with(|f| f.borrow_mut().remove(name).is_some())
+}
+
+/// Writes the given change to the aliases of the configuration file.
+/// The rest of the configuration file is kept as it is on disk, comments included.
+pub fn persist_alias(name: &str, value: Option<&str>) -> Result<(), String> {
+	let path = DeaconConfig::path().ok_or("the config directory is unknown")?;
+	DeaconConfig::save_alias(&path, name, value).map_err(|f| f.to_string())
+}
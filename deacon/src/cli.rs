@@ -0,0 +1,61 @@
+//! Command-line arguments.
+
+pub const USAGE: &str = "Usage: deacon [-q | --quiet] [--run <script> | -c <command>]
+
+Options:
+  --run <script>  Run the given script in this process and exit.
+  -c <command>    Run the given command and exit.
+  -q, --quiet     Start the shell without the banner and without reading or writing history.
+  -h, --help      Print this message.
+  -V, --version   Print the version of Deacon.
+
+If the standard input is not a terminal, it is read and run as a script.";
+
+/// What the shell should do once started.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Mode {
+    /// Start the REPL, or run the standard input if it is not a terminal.
+    Interactive,
+    /// Run the given script.
+    Run(String),
+    /// Run the given command.
+    Command(String),
+    /// Print the usage.
+    Help,
+    /// Print the version.
+    Version
+}
+
+/// The options the shell was started with.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Options {
+    pub mode: Mode,
+    /// Whether the banner and the history are disabled.
+    pub quiet: bool
+}
+
+impl Options {
+    /// Parses the given arguments, not including the name of the executable.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+        let mut options = Options { mode: Mode::Interactive, quiet: false };
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mode = match arg.as_str() {
+                "-q" | "--quiet" => {
+                    options.quiet = true;
+                    continue
+                }
+                "--run" => Mode::Run(args.next().ok_or("`--run` requires a script")?),
+                "-c" => Mode::Command(args.next().ok_or("`-c` requires a command")?),
+                "-h" | "--help" => Mode::Help,
+                "-V" | "--version" => Mode::Version,
+                _ => return Err(format!("Unknown argument \"{}\"", arg))
+            };
+            if options.mode != Mode::Interactive {
+                return Err("Only one of `--run`, `-c`, `--help` and `--version` can be given".to_string());
+            }
+            options.mode = mode;
+        }
+        Ok(options)
+    }
+}
@@ -0,0 +1,38 @@
+//! The interface of builtins.
+
+use std::io::{Read, Write};
+
+/// The standard streams of a builtin. In a pipeline or with redirections, they are connected
+/// to the other commands or to files, just like the streams of a process.
+pub struct Streams<'a> {
+	pub stdin: &'a mut dyn Read,
+	pub stdout: &'a mut dyn Write,
+	pub stderr: &'a mut dyn Write
+}
+
+/// A command which runs inside the shell instead of in a process of its own.
+/// Every builtin is listed in the [registry](super::BUILTINS).
+pub trait Builtin: Sync {
+	/// The name the builtin is called by.
+	fn name(&self) -> &'static str;
+
+	/// What the builtin does, shown by `help`.
+	fn help(&self) -> &'static str;
+
+	/// How the builtin is called, such as `cd (path)`. Required arguments are in angle brackets,
+	/// and optional arguments are in parentheses.
+	fn usage(&self) -> &'static str;
+
+	/// An example of a call, shown by `help`.
+	fn example(&self) -> &'static str {
+		self.name()
+	}
+
+	/// Whether the builtin can be called. The name of a disabled builtin refers to an executable instead.
+	fn is_enabled(&self) -> bool {
+		true
+	}
+
+	/// Runs the builtin with the expanded arguments after its name, returning its exit code.
+	fn run(&self, args: &[String], streams: &mut Streams) -> i32;
+}
@@ -1,40 +1,287 @@
 //! Internal commands.
 
+use std::cell::RefCell;
 use std::env;
-use std::path::PathBuf;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 use std::time::UNIX_EPOCH;
 use ansi_term::Colour::*;
 use byte_unit::Byte;
 use chrono::{Local, NaiveDateTime};
 use comfy_table::presets::UTF8_FULL;
 use comfy_table::Table;
+use deacon_engine::interpreter::Interpreter;
+use deacon_parse::function::Function;
 use dirs::home_dir;
-use crate::util::print_help;
-
-// input is guaranteed to NOT be blank.
-pub fn resolve_function(input: impl AsRef<str>) -> bool {
-	let input = input.as_ref();
-	if input.trim().starts_with("!") {
-		// execute literally the given process and its args
-		return false;
-	}
-	match input.split_whitespace().next().unwrap() {
-		"cd" => change_dir(input),
-		"dcinfo" => print_devcon_info(),
-		"help" => print_help(),
-		"ls" => list_dir(input),
-		"dclint" => de_lint(input),
-		"rust-panic" => panic!("You forced me to panic!"),
-		&_ => {
-			return false;
+use crate::alias::{aliases, get_alias, persist_alias, remove_alias, set_alias};
+use crate::config::CONFIG;
+use crate::env::{run_process_buffered, Shell};
+use crate::jobs::{background, foreground, kill, list_jobs, wait_jobs};
+use crate::util::{find_executable, print_help};
+
+mod builtin;
+
+pub use builtin::{Builtin, Streams};
+
+/// The registry of builtins, in the order `help` lists them.
+pub static BUILTINS: [&dyn Builtin; 16] = [
+	&Help, &ChangeDir, &ListDir, &Which, &Alias, &Unalias, &Using, &DcLint, &DcInfo,
+	&Jobs, &Fg, &Bg, &Wait, &Kill, &Not, &Exit
+];
+
+/// Returns the enabled builtins.
+pub fn builtins() -> impl Iterator<Item = &'static dyn Builtin> {
+	BUILTINS.iter().copied().filter(|f| f.is_enabled())
+}
+
+/// Returns the enabled builtin of the given name.
+pub fn get_builtin(name: &str) -> Option<&'static dyn Builtin> {
+	if name == RustPanic.name() {
+		return Some(&RustPanic);
+	}
+	builtins().find(|f| f.name() == name)
+}
+
+/// Returns whether the given name is the name of an enabled builtin.
+pub fn is_builtin(name: &str) -> bool {
+	get_builtin(name).is_some()
+}
+
+struct Help;
+
+impl Builtin for Help {
+	fn name(&self) -> &'static str { "help" }
+	fn help(&self) -> &'static str { "Print help information. With the name of a builtin, print how to use it." }
+	fn usage(&self) -> &'static str { "help (builtin)" }
+
+	fn run(&self, args: &[String], streams: &mut Streams) -> i32 {
+		let Some(name) = args.first() else {
+			return print_help(streams.stdout)
+		};
+		match get_builtin(name) {
+			Some(builtin) => {
+				let _ = writeln!(streams.stdout, "{}\n\n{}\n\nExample: {}", Cyan.bold().paint(builtin.usage()), builtin.help(), builtin.example());
+				0
+			}
+			None => {
+				let _ = writeln!(streams.stderr, "{}", Red.paint(format!("There is no builtin named \"{}\".", name)));
+				1
+			}
 		}
 	}
-	return true
 }
 
-pub fn change_dir(input: impl AsRef<str>) {
-    let input = input.as_ref();
-    let path = input.split_whitespace().skip(1).next();
+struct ChangeDir;
+
+impl Builtin for ChangeDir {
+	fn name(&self) -> &'static str { "cd" }
+	fn help(&self) -> &'static str { "Change directory to the given path. Without a path, change to the home directory." }
+	fn usage(&self) -> &'static str { "cd (path)" }
+	fn example(&self) -> &'static str { "cd /" }
+
+	fn run(&self, args: &[String], streams: &mut Streams) -> i32 {
+		change_dir(args, streams.stderr)
+	}
+}
+
+struct ListDir;
+
+impl Builtin for ListDir {
+	fn name(&self) -> &'static str { "ls" }
+	fn help(&self) -> &'static str { "List the given directory's files. If a directory is not provided, it defaults to the current working directory." }
+	fn usage(&self) -> &'static str { "ls (path)" }
+
+	fn is_enabled(&self) -> bool {
+		CONFIG.use_deacon_dir
+	}
+
+	fn run(&self, args: &[String], streams: &mut Streams) -> i32 {
+		list_dir(args, streams.stdout, streams.stderr)
+	}
+}
+
+struct Which;
+
+impl Builtin for Which {
+	fn name(&self) -> &'static str { "which" }
+	fn help(&self) -> &'static str { "Show what each name refers to: a builtin, an alias or an executable in the `PATH`." }
+	fn usage(&self) -> &'static str { "which <name>..." }
+	fn example(&self) -> &'static str { "which ls" }
+
+	fn run(&self, args: &[String], streams: &mut Streams) -> i32 {
+		which(args, streams.stdout, streams.stderr)
+	}
+}
+
+struct Alias;
+
+impl Builtin for Alias {
+	fn name(&self) -> &'static str { "alias" }
+	fn help(&self) -> &'static str { "List the aliases, or define an alias. With `--save`, the alias is also saved to the configuration file." }
+	fn usage(&self) -> &'static str { "alias (name=value) (--save)" }
+	fn example(&self) -> &'static str { "alias ll=\"ls -la\"" }
+
+	fn run(&self, args: &[String], streams: &mut Streams) -> i32 {
+		define_alias(args, streams.stdout, streams.stderr)
+	}
+}
+
+struct Unalias;
+
+impl Builtin for Unalias {
+	fn name(&self) -> &'static str { "unalias" }
+	fn help(&self) -> &'static str { "Remove an alias. With `--save`, the alias is also removed from the configuration file." }
+	fn usage(&self) -> &'static str { "unalias <name> (--save)" }
+	fn example(&self) -> &'static str { "unalias ll" }
+
+	fn run(&self, args: &[String], streams: &mut Streams) -> i32 {
+		undefine_alias(args, streams.stderr)
+	}
+}
+
+struct Using;
+
+impl Builtin for Using {
+	fn name(&self) -> &'static str { "using" }
+	fn help(&self) -> &'static str { "Run the given script in the current shell, making its exported functions available." }
+	fn usage(&self) -> &'static str { "using <script>" }
+	fn example(&self) -> &'static str { "using ./hello.dc" }
+
+	fn run(&self, args: &[String], streams: &mut Streams) -> i32 {
+		use_script(args, streams.stderr)
+	}
+}
+
+struct DcLint;
+
+impl Builtin for DcLint {
+	fn name(&self) -> &'static str { "dclint" }
+	fn help(&self) -> &'static str { "Check the given script, or the standard input, for parsing errors without running it." }
+	fn usage(&self) -> &'static str { "dclint (script)" }
+	fn example(&self) -> &'static str { "dclint ./hello.dc" }
+
+	fn run(&self, args: &[String], streams: &mut Streams) -> i32 {
+		de_lint(args, streams.stdin, streams.stdout, streams.stderr)
+	}
+}
+
+struct DcInfo;
+
+impl Builtin for DcInfo {
+	fn name(&self) -> &'static str { "dcinfo" }
+	fn help(&self) -> &'static str { "Print the version of Deacon, and whether it is a debug build." }
+	fn usage(&self) -> &'static str { "dcinfo" }
+
+	fn run(&self, _: &[String], streams: &mut Streams) -> i32 {
+		print_devcon_info(streams.stdout)
+	}
+}
+
+struct Jobs;
+
+impl Builtin for Jobs {
+	fn name(&self) -> &'static str { "jobs" }
+	fn help(&self) -> &'static str { "List the jobs, that is, pipelines started with `&` or stopped with Ctrl-Z." }
+	fn usage(&self) -> &'static str { "jobs" }
+
+	fn run(&self, _: &[String], streams: &mut Streams) -> i32 {
+		list_jobs(streams.stdout)
+	}
+}
+
+struct Fg;
+
+impl Builtin for Fg {
+	fn name(&self) -> &'static str { "fg" }
+	fn help(&self) -> &'static str { "Continue a job in the foreground. Without a job, the newest job is used." }
+	fn usage(&self) -> &'static str { "fg (%job)" }
+	fn example(&self) -> &'static str { "fg %1" }
+
+	fn run(&self, args: &[String], streams: &mut Streams) -> i32 {
+		foreground(args, streams.stdout, streams.stderr)
+	}
+}
+
+struct Bg;
+
+impl Builtin for Bg {
+	fn name(&self) -> &'static str { "bg" }
+	fn help(&self) -> &'static str { "Continue a stopped job in the background. Without a job, the newest job is used." }
+	fn usage(&self) -> &'static str { "bg (%job)" }
+	fn example(&self) -> &'static str { "bg %1" }
+
+	fn run(&self, args: &[String], streams: &mut Streams) -> i32 {
+		background(args, streams.stdout, streams.stderr)
+	}
+}
+
+struct Wait;
+
+impl Builtin for Wait {
+	fn name(&self) -> &'static str { "wait" }
+	fn help(&self) -> &'static str { "Wait until the given jobs, or every job, finish. Ctrl-C stops waiting." }
+	fn usage(&self) -> &'static str { "wait (%job...)" }
+	fn example(&self) -> &'static str { "wait %1" }
+
+	fn run(&self, args: &[String], streams: &mut Streams) -> i32 {
+		wait_jobs(args, streams.stderr)
+	}
+}
+
+struct Kill;
+
+impl Builtin for Kill {
+	fn name(&self) -> &'static str { "kill" }
+	fn help(&self) -> &'static str { "Send a signal to jobs or processes. The signal defaults to TERM." }
+	fn usage(&self) -> &'static str { "kill (-signal) <%job | pid>..." }
+	fn example(&self) -> &'static str { "kill -KILL %1" }
+
+	fn run(&self, args: &[String], streams: &mut Streams) -> i32 {
+		kill(args, streams.stderr)
+	}
+}
+
+struct Not;
+
+impl Builtin for Not {
+	fn name(&self) -> &'static str { "not" }
+	fn help(&self) -> &'static str { "Run the given command, failing if it succeeds and succeeding if it fails." }
+	fn usage(&self) -> &'static str { "not <command> (args)" }
+	fn example(&self) -> &'static str { "if not test -d target { cargo build }" }
+
+	fn run(&self, args: &[String], streams: &mut Streams) -> i32 {
+		negate(args, streams)
+	}
+}
+
+struct Exit;
+
+impl Builtin for Exit {
+	fn name(&self) -> &'static str { "exit" }
+	fn help(&self) -> &'static str { "Exit the shell or stop the script. Without a code, the exit code of the last command is used." }
+	fn usage(&self) -> &'static str { "exit (code)" }
+	fn example(&self) -> &'static str { "exit 1" }
+
+	fn run(&self, args: &[String], streams: &mut Streams) -> i32 {
+		request_exit(args, streams.stderr)
+	}
+}
+
+/// Panics on purpose, to check the panic handler. It is not in the registry, so `help` does not list it.
+struct RustPanic;
+
+impl Builtin for RustPanic {
+	fn name(&self) -> &'static str { "rust-panic" }
+	fn help(&self) -> &'static str { "Panic on purpose." }
+	fn usage(&self) -> &'static str { "rust-panic" }
+
+	fn run(&self, _: &[String], _: &mut Streams) -> i32 {
+		panic!("You forced me to panic!")
+	}
+}
+
+pub fn change_dir(args: &[String], stderr: &mut dyn Write) -> i32 {
+    let path = args.first();
 
     let path = if let Some(p) = path {
 	    PathBuf::from(p)
@@ -42,19 +289,21 @@ pub fn change_dir(input: impl AsRef<str>) {
         match home_dir() {
             Some(path) => path,
             None => {
-                eprintln!("{}", Red.paint(format!("Failed to change directory, no path is given and `$HOME` is not set!")));
-                return;
+                let _ = writeln!(stderr, "{}", Red.paint("Failed to change directory, no path is given and `$HOME` is not set!"));
+                return 1;
             }
         }
     };
 
     if let Err(err) = env::set_current_dir(path) {
-        eprintln!("{}", Red.paint(format!("Failed to change directory: {}", err.to_string())));
+        let _ = writeln!(stderr, "{}", Red.paint(format!("Failed to change directory: {}", err)));
+        return 1;
     }
+    0
 }
 
-pub fn print_devcon_info() {
-	println!("{} {} {}",
+pub fn print_devcon_info(stdout: &mut dyn Write) -> i32 {
+	let _ = writeln!(stdout, "{} {} {}",
 	         Yellow.bold().paint("DevCon"),
 	         Cyan.paint(env!("CARGO_PKG_VERSION")),
 	         {
@@ -65,23 +314,22 @@ pub fn print_devcon_info() {
 		         }
 	         },
 	);
-	println!("debug build: {}", crate::util::colorize_bool(cfg!(debug_assertions)));
+	let _ = writeln!(stdout, "debug build: {}", crate::util::colorize_bool(cfg!(debug_assertions)));
+	0
 }
 
-pub fn list_dir(input: impl AsRef<str>) {
+pub fn list_dir(args: &[String], stdout: &mut dyn Write, stderr: &mut dyn Write) -> i32 {
 	let mut table = Table::new();
 	table.load_preset(UTF8_FULL);
-	let mut input = input.as_ref().split_whitespace();
-	input.next().unwrap();
 	let mut dir = env::current_dir();
-	if let Some(dir_inner) = input.next() {
+	if let Some(dir_inner) = args.first() {
 		dir = Ok(PathBuf::from(dir_inner));
 	}
 	match dir {
 		Ok(dir) => {
 			if dir.is_file() {
-				println!("{}", dir.to_string_lossy());
-				return;
+				let _ = writeln!(stdout, "{}", dir.to_string_lossy());
+				return 0;
 			}
 			table.set_header(vec![
 				"File",
@@ -152,20 +400,192 @@ pub fn list_dir(input: impl AsRef<str>) {
 							]);
 						}
 					}
-					println!("{}", table);
+					let _ = writeln!(stdout, "{}", table);
+					0
 				}
 				Err(err) => {
-					eprintln!("{}", Red.paint(format!("Failed to read directory: {}", err.to_string())));
+					let _ = writeln!(stderr, "{}", Red.paint(format!("Failed to read directory: {}", err)));
+					1
 				}
 			}
 		}
 		Err(err) => {
-			eprintln!("{}", Red.paint(format!("Failed to list directory: {}", err.to_string())));
+			let _ = writeln!(stderr, "{}", Red.paint(format!("Failed to list directory: {}", err)));
+			1
 		}
 	}
 }
 
-pub fn de_lint(input: impl AsRef<str>) {
-	let success = deacon_engine::lint_script(input.as_ref().split_whitespace().skip(1).next().unwrap()).is_some();
-	println!("Lint success: {}", success);
+thread_local! {
+	/// The exported functions of the scripts run with `using`, until the interpreter takes them.
+	static IMPORTS: RefCell<Vec<Function>> = const { RefCell::new(vec![]) };
+}
+
+/// Runs the given script in the current process, making its exported functions available to the shell.
+/// The exit code is the script's, or 2 if it fails to parse.
+pub fn use_script(args: &[String], stderr: &mut dyn Write) -> i32 {
+	let Some(path) = args.first() else {
+		let _ = writeln!(stderr, "{}", Red.paint("No script is given to `using`!"));
+		return 2
+	};
+	if !Path::new(path).is_file() {
+		let _ = writeln!(stderr, "{}", Red.paint(format!("Failed to use \"{}\": no such script", path)));
+		return 1
+	}
+	let mut interpreter = Interpreter::new(Shell);
+	match deacon_engine::run_script(path, &mut interpreter) {
+		Some(code) => {
+			IMPORTS.with(|f| f.borrow_mut().extend(interpreter.exported_functions().cloned()));
+			code
+		}
+		None => 2
+	}
+}
+
+/// Takes the functions exported by the scripts run with `using` since the last call.
+pub fn take_imports() -> Vec<Function> {
+	IMPORTS.with(|f| f.take())
+}
+
+/// Lists the aliases, prints one alias, or defines an alias with `alias name=value`.
+/// With `--save`, the alias is also written to the configuration file.
+pub fn define_alias(args: &[String], stdout: &mut dyn Write, stderr: &mut dyn Write) -> i32 {
+	let save = args.iter().any(|f| f == "--save");
+	let args = args.iter().filter(|f| *f != "--save").collect::<Vec<&String>>();
+	let Some(arg) = args.first() else {
+		for (name, value) in aliases() {
+			let _ = writeln!(stdout, "{} = \"{}\"", Cyan.paint(name), value);
+		}
+		return 0
+	};
+	let Some((name, value)) = arg.split_once('=') else {
+		return match get_alias(arg) {
+			Some(value) => {
+				let _ = writeln!(stdout, "{} = \"{}\"", Cyan.paint(arg.as_str()), value);
+				0
+			}
+			None => {
+				let _ = writeln!(stderr, "{}", Red.paint(format!("There is no alias named \"{}\".", arg)));
+				1
+			}
+		}
+	};
+	// `alias ll=ls -la` is the same as `alias ll="ls -la"`.
+	let value = std::iter::once(value).chain(args[1..].iter().map(|f| f.as_str())).collect::<Vec<&str>>().join(" ");
+	if let Err(err) = set_alias(name, &value) {
+		let _ = writeln!(stderr, "{}", Red.paint(format!("Failed to define the alias: {}.", err)));
+		return 1
+	}
+	if save && let Err(err) = persist_alias(name, Some(&value)) {
+		let _ = writeln!(stderr, "{}", Red.paint(format!("Failed to save the alias: {}.", err)));
+		return 1
+	}
+	0
+}
+
+/// Removes the given aliases. With `--save`, they are also removed from the configuration file.
+pub fn undefine_alias(args: &[String], stderr: &mut dyn Write) -> i32 {
+	let save = args.iter().any(|f| f == "--save");
+	let names = args.iter().filter(|f| *f != "--save").collect::<Vec<&String>>();
+	if names.is_empty() {
+		let _ = writeln!(stderr, "{}", Red.paint("No alias is given to `unalias`!"));
+		return 2
+	}
+	let mut code = 0;
+	for name in names {
+		if !remove_alias(name) {
+			let _ = writeln!(stderr, "{}", Red.paint(format!("There is no alias named \"{}\".", name)));
+			code = 1;
+		} else if save && let Err(err) = persist_alias(name, None) {
+			let _ = writeln!(stderr, "{}", Red.paint(format!("Failed to save the removal of the alias: {}.", err)));
+			code = 1;
+		}
+	}
+	code
+}
+
+/// Lints the given script, or the standard input without a script.
+pub fn de_lint(args: &[String], stdin: &mut dyn Read, stdout: &mut dyn Write, stderr: &mut dyn Write) -> i32 {
+	let success = match args.first() {
+		Some(path) => deacon_engine::lint_script(path).is_some(),
+		None => {
+			let mut source = String::new();
+			if let Err(err) = stdin.read_to_string(&mut source) {
+				let _ = writeln!(stderr, "{}", Red.paint(format!("Failed to read the standard input: {}", err)));
+				return 1
+			}
+			match deacon_parse::parse_script(source.as_str()) {
+				Ok(_) => true,
+				Err(err) => {
+					deacon_engine::report_parse_error("stdin", &source, &err);
+					false
+				}
+			}
+		}
+	};
+	let _ = writeln!(stdout, "Lint success: {}", success);
+	if success { 0 } else { 1 }
+}
+
+thread_local! {
+	/// The exit requested by the `exit` builtin, until the interpreter takes it.
+	static EXIT: RefCell<Option<Option<i32>>> = const { RefCell::new(None) };
+}
+
+/// Requests the interpreter to stop with the given exit code, or with the exit code of the last command.
+pub fn request_exit(args: &[String], stderr: &mut dyn Write) -> i32 {
+	let code = match args.first().map(|f| f.parse::<i32>()) {
+		None => None,
+		Some(Ok(code)) => Some(code),
+		Some(Err(_)) => {
+			let _ = writeln!(stderr, "{}", Red.paint(format!("exit: \"{}\" is not an exit code", args[0])));
+			return 2
+		}
+	};
+	EXIT.with(|f| *f.borrow_mut() = Some(code));
+	code.unwrap_or(0)
+}
+
+/// Takes the exit requested by the `exit` builtin since the last call.
+pub fn take_exit() -> Option<Option<i32>> {
+	EXIT.with(|f| f.take())
+}
+
+/// Prints what each name refers to. The exit code is 1 if any name refers to nothing.
+pub fn which(args: &[String], stdout: &mut dyn Write, stderr: &mut dyn Write) -> i32 {
+	if args.is_empty() {
+		let _ = writeln!(stderr, "{}", Red.paint("No name is given to `which`!"));
+		return 2
+	}
+	let mut code = 0;
+	for name in args {
+		if let Some(value) = get_alias(name) {
+			let _ = writeln!(stdout, "{}: aliased to \"{}\"", name, value);
+		} else if is_builtin(name) {
+			let _ = writeln!(stdout, "{}: shell builtin", name);
+		} else if let Some(path) = find_executable(name.strip_prefix('!').unwrap_or(name)) {
+			let _ = writeln!(stdout, "{}", path.to_string_lossy());
+		} else {
+			let _ = writeln!(stderr, "{}", Red.paint(format!("{}: not found", name)));
+			code = 1;
+		}
+	}
+	code
+}
+
+/// Runs the given builtin or process, returning 1 if it succeeds and 0 if it fails.
+/// The interpreter negates calls by itself, so this only runs as a stage of a pipeline.
+pub fn negate(args: &[String], streams: &mut Streams) -> i32 {
+	let Some(name) = args.first() else {
+		let _ = writeln!(streams.stderr, "{}", Red.paint("No command is given to `not`!"));
+		return 2
+	};
+	let code = match get_builtin(name) {
+		Some(builtin) => builtin.run(&args[1..], streams),
+		None => run_process_buffered(args, streams)
+	};
+	match code {
+		0 => 1,
+		_ => 0
+	}
 }
@@ -0,0 +1,82 @@
+//! Tab completion.
+
+use std::collections::{BTreeSet, HashSet};
+use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use rustyline::Context;
+use deacon_parse::lexer::{tokenize, TokenKind};
+use crate::alias::aliases;
+use crate::commands::builtins;
+use crate::util::executables;
+
+/// Completes command names, environment variables and filenames.
+///
+/// The first word of a command is completed with the names of builtins, aliases, functions and
+/// executables in the `PATH`. Inside `?...?`, the names of environment variables are completed.
+/// Any other word is completed as a filename.
+#[derive(Default)]
+pub struct DeaconCompleter {
+	filenames: FilenameCompleter
+}
+
+impl DeaconCompleter {
+	/// Completes the word before the cursor, given the names of the functions of the session.
+	/// Returns the start of the completed word along with the candidates.
+	pub fn complete(&self, line: &str, pos: usize, ctx: &Context<'_>, functions: &HashSet<String>) -> rustyline::Result<(usize, Vec<Pair>)> {
+		let before = &line[..pos];
+		// an unclosed quote means the cursor is in a quoted argument, so only filenames make sense.
+		let Ok(tokens) = tokenize(before) else {
+			return self.filenames.complete(line, pos, ctx);
+		};
+		let (start, previous) = match tokens.split_last() {
+			Some((last, previous)) if last.kind == TokenKind::Word && last.span.end == pos => (last.span.start, previous.last()),
+			_ => (pos, tokens.last())
+		};
+		let word = &before[start..];
+		// an odd number of `?` means the cursor is inside an environment variable.
+		if let Some(open) = word.rfind('?').filter(|_| word.matches('?').count() % 2 == 1) {
+			return Ok((start + open + 1, complete_env_var(&word[open + 1..])));
+		}
+		let is_command = previous.is_none_or(|f| {
+			matches!(f.kind, TokenKind::Pipe | TokenKind::And | TokenKind::Or | TokenKind::Semicolon | TokenKind::Background)
+				// a command follows these keywords, builtins and the start of a block.
+				|| matches!(f.text, "if" | "else" | "while" | "not" | "{")
+		});
+		if is_command && !word.contains(['/', '\\']) {
+			Ok((start, complete_command(word, functions)))
+		} else {
+			self.filenames.complete(line, pos, ctx)
+		}
+	}
+}
+
+/// Completes the name of a command. With the `!` escape syntax, only executables are completed.
+fn complete_command(word: &str, functions: &HashSet<String>) -> Vec<Pair> {
+	if let Some(prefix) = word.strip_prefix('!') {
+		return executables(prefix).into_iter()
+			.map(|f| Pair { replacement: format!("!{}", f), display: f })
+			.collect();
+	}
+	let mut names = BTreeSet::new();
+	names.extend(builtins().map(|f| f.name().to_string()));
+	names.insert("clear".to_string());
+	names.extend(aliases().into_iter().map(|f| f.0));
+	names.extend(functions.iter().cloned());
+	names.retain(|f| f.starts_with(word));
+	// builtins, aliases and functions come before executables, since they shadow them.
+	let mut candidates = names.iter().cloned().collect::<Vec<String>>();
+	candidates.extend(executables(word).into_iter().filter(|f| !names.contains(f)));
+	candidates.into_iter()
+		.map(|f| Pair { display: f.clone(), replacement: f })
+		.collect()
+}
+
+/// Completes the name of an environment variable, closing the `?`.
+fn complete_env_var(prefix: &str) -> Vec<Pair> {
+	let names = std::env::vars_os()
+		.map(|f| f.0.to_string_lossy().to_string())
+		.filter(|f| f.starts_with(prefix))
+		.collect::<BTreeSet<String>>();
+	names.into_iter()
+		.map(|f| Pair { replacement: format!("{}?", f), display: f })
+		.collect()
+}
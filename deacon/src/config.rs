@@ -0,0 +1,18 @@
+//! The configuration of the shell.
+
+use ansi_term::Colour::Red;
+use deacon_config::DeaconConfig;
+use lazy_static::lazy_static;
+
+lazy_static! {
+	/// The configuration, which is loaded when it is first used.
+	pub static ref CONFIG: DeaconConfig = load_config();
+}
+
+fn load_config() -> DeaconConfig {
+	DeaconConfig::load().unwrap_or_else(|err| {
+		let path = DeaconConfig::path().map_or("".to_string(), |f| f.to_string_lossy().to_string());
+		eprintln!("{}", Red.paint(format!("Failed to load the configuration at \"{}\": {}\nThe default configuration is used instead.", path, err)));
+		DeaconConfig::default()
+	})
+}
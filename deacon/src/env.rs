@@ -1,59 +1,440 @@
 //! Environment management and process execution.
 
+use std::cell::RefCell;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{PipeReader, Read, Write};
 use std::process::*;
+use std::rc::Rc;
 use ansi_term::Colour::Red;
+use deacon_engine::interpreter::Host;
+use deacon_engine::scope::Scope;
+use deacon_parse::ast::{Expr, Redirect};
+use deacon_parse::function::Function;
+use deacon_parse::lexer::{word_parts, Quote, WordPart};
+use crate::alias::expand_aliases;
+use crate::commands::{get_builtin, take_exit, take_imports, Streams};
+use crate::jobs::{add_background, wait_foreground, JobState};
+use crate::signals;
 
-/// Executes a process. Printing to the console is not done.
-pub fn execute_process(input: impl ToString) -> Option<(Command, Child)> {
-	let mut input = input.to_string();
-	if input.starts_with("!") {
-		input = input.replacen("!", "", 1);
-	}
-	input = input.replace("\\", "\\\\");
-	input = input.replace("\\\\\"", "\\\"");
-	input = input.replace("\\\\\\\\", "\\\\");
-	match shell_words::split(input.as_str()) {
-		Ok(split) => {
-			let split: Vec<String> = split;
-			let mut iter = split.iter();
-			if iter.is_empty() {
-				// there is nothing; this is therefore invalid syntax
-				return None;
+/// Executes a process with the given arguments and standard streams. Printing to the console is not done.
+/// The first argument is the name of the process; an exclamation mark before it is ignored.
+///
+/// The process joins the given process group, or leads a new one, if job control is enabled.
+/// A process in the foreground takes the terminal.
+pub fn execute_process(argv: &[String], stdin: Stdio, stdout: Stdio, stderr: Stdio, group: Option<u32>, foreground: bool) -> Option<(Command, Child)> {
+	let (command_name, args) = argv.split_first()?;
+	let command_name = command_name.strip_prefix('!').unwrap_or(command_name);
+	let mut command = Command::new(command_name);
+	command.args(args);
+	signals::prepare(&mut command, group, foreground);
+	match command.stdout(stdout).stdin(stdin).stderr(stderr).spawn() {
+		Ok(child) => {
+			Some((command, child))
+		}
+		Err(err) => {
+			eprintln!("{}", Red.paint(format!("Failed to execute \"{}\": {}", command_name, err)));
+			None
+		}
+	}
+}
+
+/// Expands a word of a call into the argument it represents. This removes quotes and escaping backslashes,
+/// substitutes environment variables and variables of the given scope outside of single quotes,
+/// and replaces a leading unquoted `~` with the home directory.
+pub fn expand_word(word: &str, scope: &Scope) -> String {
+	word_parts(word).iter().enumerate().map(|(index, part)| expand_part(index, part, scope)).collect()
+}
+
+/// Expands a word of a `for` loop into its items. A word with an unquoted `*` or `[` is a glob, which
+/// expands to the matching paths in order; a glob without any match is kept as a single item.
+pub fn expand_items(word: &str, scope: &Scope) -> Vec<String> {
+	let parts = word_parts(word);
+	if !parts.iter().any(|f| f.quote == Quote::None && f.text.contains(['*', '['])) {
+		return vec![expand_word(word, scope)];
+	}
+	// quoted parts of the glob match literally.
+	let pattern = parts.iter().enumerate()
+		.map(|(index, part)| match part.quote {
+			Quote::None => expand_part(index, part, scope),
+			Quote::Single | Quote::Double => glob::Pattern::escape(&expand_part(index, part, scope))
+		})
+		.collect::<String>();
+	let paths = glob::glob(&pattern)
+		.map(|paths| paths.filter_map(Result::ok).map(|f| f.to_string_lossy().into_owned()).collect::<Vec<String>>())
+		.unwrap_or_default();
+	if paths.is_empty() {
+		vec![expand_word(word, scope)]
+	} else {
+		paths
+	}
+}
+
+/// Expands a part of a word, where `index` is the position of the part in the word.
+fn expand_part(index: usize, part: &WordPart, scope: &Scope) -> String {
+	match part.quote {
+		Quote::Single => part.text.clone(),
+		Quote::Double => scope.substitute(&substitute_env_var(&part.text)),
+		Quote::None => {
+			let mut text = scope.substitute(&substitute_env_var(&part.text));
+			if index == 0 && (text == "~" || text.starts_with("~/") || text.starts_with("~\\"))
+				&& let Some(home) = dirs::home_dir() {
+				text.replace_range(..1, &home.to_string_lossy());
+			}
+			text
+		}
+	}
+}
+
+/// Executes an expression, that is, a command, a pipeline or a command list, returning its exit code.
+pub fn execute_expr(expr: &Expr, scope: &Scope) -> i32 {
+	run_expr(expr, scope, &mut None)
+}
+
+/// Executes an expression like [`execute_expr`], but returns its standard output along with its exit code
+/// instead of printing it. The standard output of a background job is not captured.
+pub fn capture_expr(expr: &Expr, scope: &Scope) -> (i32, String) {
+	let mut output = Some(vec![]);
+	let code = run_expr(expr, scope, &mut output);
+	(code, String::from_utf8_lossy(&output.unwrap_or_default()).into_owned())
+}
+
+/// Executes an expression. If an output is given, the standard output of the expression is appended to it.
+fn run_expr(expr: &Expr, scope: &Scope, output: &mut Option<Vec<u8>>) -> i32 {
+	match expr {
+		Expr::Command(command) => execute_pipeline(std::slice::from_ref(command), scope, None, output),
+		Expr::Pipeline(pipeline) => execute_pipeline(&pipeline.commands, scope, None, output),
+		Expr::And(left, right) => match run_expr(left, scope, output) {
+			0 => run_expr(right, scope, output),
+			code => code
+		},
+		Expr::Or(left, right) => match run_expr(left, scope, output) {
+			0 => 0,
+			_ => run_expr(right, scope, output)
+		},
+		Expr::Background(pipeline) => {
+			let (children, code, _) = spawn_pipeline(&pipeline.commands, scope, StageOutput::None, false, false);
+			if children.is_empty() {
+				return code;
+			}
+			add_background(children.into_iter().map(|f| f.0).collect(), describe(&pipeline.commands));
+			0
+		}
+	}
+}
+
+/// The [host](Host) of the shell's interpreter, which runs builtins and processes.
+pub struct Shell;
+
+impl Host for Shell {
+	fn execute(&mut self, expr: &Expr, scope: &Scope) -> i32 {
+		execute_expr(expr, scope)
+	}
+
+	fn capture(&mut self, expr: &Expr, scope: &Scope) -> (i32, String) {
+		capture_expr(expr, scope)
+	}
+
+	fn execute_piped(&mut self, commands: &[deacon_parse::ast::Command], scope: &Scope, input: String, capture: bool) -> (i32, String) {
+		let mut output = capture.then(Vec::new);
+		let code = execute_pipeline(commands, scope, Some(input.into_bytes()), &mut output);
+		(code, String::from_utf8_lossy(&output.unwrap_or_default()).into_owned())
+	}
+
+	fn expand(&self, word: &str, scope: &Scope) -> String {
+		expand_word(word, scope)
+	}
+
+	fn expand_items(&self, word: &str, scope: &Scope) -> Vec<String> {
+		expand_items(word, scope)
+	}
+
+	fn expand_aliases(&self, words: Vec<String>) -> Vec<String> {
+		expand_aliases(words)
+	}
+
+	fn is_interrupted(&mut self) -> bool {
+		signals::take_interrupt()
+	}
+
+	fn take_imports(&mut self) -> Vec<Function> {
+		take_imports()
+	}
+
+	fn take_exit(&mut self) -> Option<Option<i32>> {
+		take_exit()
+	}
+}
+
+/// The output of the previous stage of a pipeline.
+enum StageOutput {
+	/// There is no previous stage.
+	None,
+	/// The previous stage was a process.
+	Process(PipeReader),
+	/// The previous stage was a builtin, whose output was buffered.
+	Builtin(Vec<u8>),
+	/// The previous stage's output was redirected to a file, so there is nothing to read.
+	Closed
+}
+
+/// The files that a command's standard streams are redirected to.
+#[derive(Default)]
+struct Redirection {
+	stdin: Option<File>,
+	stdout: Option<File>,
+	stderr: Option<File>,
+	stderr_to_stdout: bool
+}
+
+impl Redirection {
+	/// Opens the files of the given redirections, whose paths are [expanded](expand_word) like arguments.
+	/// Later redirections of the same stream take precedence.
+	fn open(redirects: &[Redirect], scope: &Scope) -> io::Result<Redirection> {
+		let create = |path: &str, append: bool| {
+			let path = expand_word(path, scope);
+			OpenOptions::new().write(true).create(true).append(append).truncate(!append).open(&path)
+				.map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path, err)))
+		};
+		let mut redirection = Redirection::default();
+		for redirect in redirects {
+			match redirect {
+				Redirect::Stdin(path) => {
+					let path = expand_word(path, scope);
+					redirection.stdin = Some(File::open(&path).map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path, err)))?);
+				}
+				Redirect::Stdout { path, append } => redirection.stdout = Some(create(path, *append)?),
+				Redirect::Stderr { path, append } => redirection.stderr = Some(create(path, *append)?),
+				Redirect::Both { path, append } => {
+					let file = create(path, *append)?;
+					redirection.stderr = Some(file.try_clone()?);
+					redirection.stdout = Some(file);
+				}
+				Redirect::StderrToStdout => redirection.stderr_to_stdout = true
 			}
-			let command_name = iter.next();
-			let mut command = Command::new(command_name.unwrap());
-			for i in iter {
-				command.arg(i);
+		}
+		Ok(redirection)
+	}
+}
+
+/// A writer shared between a builtin's standard output and standard error, for `2>&1`.
+struct SharedWriter<'a>(Rc<RefCell<Box<dyn Write + 'a>>>);
+
+impl Write for SharedWriter<'_> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.0.borrow_mut().write(buf)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.0.borrow_mut().flush()
+	}
+}
+
+/// Executes a pipeline, connecting the standard output of each command to the standard input of the next.
+/// Builtins run in-process, and their output is buffered before being fed into the next command.
+/// If an input is given, the first command reads it as if it were the buffered output of a previous stage.
+/// If an output is given, the standard output of the last command is appended to it.
+///
+/// The exit code of a pipeline is the exit code of its last command. If the pipeline is stopped,
+/// it becomes a [job](crate::jobs).
+pub fn execute_pipeline(commands: &[deacon_parse::ast::Command], scope: &Scope, input: Option<Vec<u8>>, output: &mut Option<Vec<u8>>) -> i32 {
+	let input = input.map_or(StageOutput::None, StageOutput::Builtin);
+	let (children, mut code, last) = spawn_pipeline(commands, scope, input, true, output.is_some());
+	if let Some(output) = output {
+		// read everything before waiting, so that the last command cannot block on a full pipe.
+		match last {
+			StageOutput::Process(mut reader) => {
+				let _ = reader.read_to_end(output);
 			}
-			match command.stdout(Stdio::inherit()).stdin(Stdio::inherit()).spawn() {
-				Ok(child) => {
-					Some((command, child))
+			StageOutput::Builtin(buffer) => output.extend(buffer),
+			StageOutput::None | StageOutput::Closed => {}
+		}
+	}
+	if children.is_empty() {
+		return code;
+	}
+	let last_is_process = matches!(children.last(), Some((_, true)));
+	match wait_foreground(children.into_iter().map(|f| f.0).collect(), describe(commands)) {
+		JobState::Stopped(status) => code = status,
+		JobState::Done(status) if last_is_process => code = status,
+		_ => {}
+	}
+	signals::record_interrupt(code);
+	code
+}
+
+/// Describes a pipeline for the job table.
+fn describe(commands: &[deacon_parse::ast::Command]) -> String {
+	commands.iter()
+		.map(|f| std::iter::once(&f.call.name).chain(f.call.args.iter()).cloned().collect::<Vec<String>>().join(" "))
+		.collect::<Vec<String>>()
+		.join(" | ")
+}
+
+/// Runs the builtins of a pipeline and spawns its processes, without waiting for them, with the given input
+/// as the output of the stage before the first command. Returns the processes, along with whether each one is
+/// the last command, the exit code of the pipeline if its last command is not a process, and the output of
+/// the last command if it is captured.
+fn spawn_pipeline(commands: &[deacon_parse::ast::Command], scope: &Scope, input: StageOutput, foreground: bool, capture: bool) -> (Vec<(Child, bool)>, i32, StageOutput) {
+	let mut children: Vec<(Child, bool)> = vec![];
+	let mut code = 0;
+	let mut previous = input;
+	for (index, command) in commands.iter().enumerate() {
+		let is_last = index == commands.len() - 1;
+		// the output of the last command goes to the terminal, unless it is captured.
+		let to_terminal = is_last && !capture;
+		let call = &command.call;
+		let argv = std::iter::once(&call.name).chain(call.args.iter()).map(|f| expand_word(f, scope)).collect::<Vec<String>>();
+		let mut redirection = match Redirection::open(&command.redirects, scope) {
+			Ok(redirection) => redirection,
+			Err(err) => {
+				eprintln!("{}", Red.paint(format!("Failed to redirect: {}", err)));
+				previous = StageOutput::Closed;
+				code = 1;
+				continue
+			}
+		};
+		if let Some(builtin) = argv.first().and_then(|f| get_builtin(f)) {
+			let mut buffer = vec![];
+			{
+				let mut stdin: Box<dyn Read> = match (redirection.stdin.take(), std::mem::replace(&mut previous, StageOutput::None)) {
+					(Some(file), _) => Box::new(file),
+					(None, StageOutput::None) => Box::new(io::stdin()),
+					(None, StageOutput::Process(reader)) => Box::new(reader),
+					(None, StageOutput::Builtin(output)) => Box::new(io::Cursor::new(output)),
+					(None, StageOutput::Closed) => Box::new(io::empty())
+				};
+				let stdout: Box<dyn Write> = match redirection.stdout.as_ref().and_then(|f| f.try_clone().ok()) {
+					Some(file) => Box::new(file),
+					None if to_terminal => Box::new(io::stdout()),
+					None => Box::new(&mut buffer)
+				};
+				let stdout = Rc::new(RefCell::new(stdout));
+				let mut stderr: Box<dyn Write> = match redirection.stderr.as_ref().and_then(|f| f.try_clone().ok()) {
+					Some(file) => Box::new(file),
+					None if redirection.stderr_to_stdout => Box::new(SharedWriter(stdout.clone())),
+					None => Box::new(io::stderr())
+				};
+				code = builtin.run(&argv[1..], &mut Streams { stdin: &mut stdin, stdout: &mut SharedWriter(stdout), stderr: &mut stderr });
+			}
+			previous = if redirection.stdout.is_some() {
+				StageOutput::Closed
+			} else if to_terminal {
+				StageOutput::None
+			} else {
+				StageOutput::Builtin(buffer)
+			};
+			continue
+		}
+		// execute a process
+		let (stdin, buffered) = match (redirection.stdin.take(), std::mem::replace(&mut previous, StageOutput::None)) {
+			(Some(file), _) => (Stdio::from(file), None),
+			// without job control, a background job could compete with the shell for the terminal.
+			(None, StageOutput::None) if foreground || signals::is_enabled() => (Stdio::inherit(), None),
+			(None, StageOutput::None) => (Stdio::null(), None),
+			(None, StageOutput::Process(out)) => (Stdio::from(out), None),
+			(None, StageOutput::Builtin(buffer)) => (Stdio::piped(), Some(buffer)),
+			(None, StageOutput::Closed) => (Stdio::null(), None)
+		};
+		// the copy of the standard output is where the standard error goes for `2>&1`.
+		let (stdout, stdout_copy) = if let Some(file) = redirection.stdout.take() {
+			previous = StageOutput::Closed;
+			let copy = file.try_clone().ok().map(Stdio::from);
+			(Stdio::from(file), copy)
+		} else if to_terminal {
+			// the shell's own standard output, which may be a file rather than the terminal.
+			(Stdio::inherit(), Some(Stdio::from(io::stdout())))
+		} else {
+			match io::pipe() {
+				Ok((reader, writer)) => {
+					previous = StageOutput::Process(reader);
+					let copy = writer.try_clone().ok().map(Stdio::from);
+					(Stdio::from(writer), copy)
 				}
 				Err(err) => {
-					eprintln!("{}", Red.paint(format!("Failed to execute \"{}\": {}", command_name.unwrap(), err.to_string())));
-					None
+					eprintln!("{}", Red.paint(format!("Failed to create a pipe: {}", err)));
+					code = 1;
+					break
 				}
 			}
-		},
-		Err(_) => {
-			eprintln!("{}", Red.paint("Unclosed quotes."));
-			None
+		};
+		let stderr = match redirection.stderr.take() {
+			Some(file) => Stdio::from(file),
+			None if redirection.stderr_to_stdout => stdout_copy.unwrap_or(Stdio::inherit()),
+			None => Stdio::inherit()
+		};
+		let group = children.first().map(|f| f.0.id());
+		if let Some((_, mut child)) = execute_process(&argv, stdin, stdout, stderr, group, foreground) {
+			if let Some(buffer) = buffered && let Some(mut stdin) = child.stdin.take() {
+				// write on another thread so a full pipe cannot block the shell.
+				std::thread::spawn(move || {
+					let _ = stdin.write_all(&buffer);
+				});
+			}
+			children.push((child, is_last));
+		} else {
+			// the process could not be spawned, which usually means it does not exist.
+			code = 127;
+		}
+	}
+	(children, code, previous)
+}
+
+/// Runs a process in place of a builtin, returning its exit code. Like a builtin, the process reads all
+/// of its input up front, and its output is copied to the given streams once it exits.
+pub fn run_process_buffered(argv: &[String], streams: &mut Streams) -> i32 {
+	let mut input = vec![];
+	if let Err(err) = streams.stdin.read_to_end(&mut input) {
+		let _ = writeln!(streams.stderr, "{}", Red.paint(format!("Failed to read the standard input: {}", err)));
+		return 1;
+	}
+	let Some((_, mut child)) = execute_process(argv, Stdio::piped(), Stdio::piped(), Stdio::piped(), None, false) else {
+		return 127;
+	};
+	if let Some(mut stdin) = child.stdin.take() {
+		// write on another thread so a full pipe cannot block the shell.
+		std::thread::spawn(move || {
+			let _ = stdin.write_all(&input);
+		});
+	}
+	match child.wait_with_output() {
+		Ok(output) => {
+			let _ = streams.stdout.write_all(&output.stdout);
+			let _ = streams.stderr.write_all(&output.stderr);
+			exit_code(output.status)
+		}
+		Err(err) => {
+			let _ = writeln!(streams.stderr, "{}", Red.paint(format!("Failed to wait for \"{}\": {}", argv[0], err)));
+			1
 		}
 	}
 }
 
+/// Returns the exit code of a process. A process which was killed or stopped by a signal
+/// has the exit code 128 plus the number of the signal, as in other shells.
+pub fn exit_code(status: ExitStatus) -> i32 {
+	#[cfg(unix)]
+	{
+		use std::os::unix::process::ExitStatusExt;
+		if let Some(signal) = status.signal().or(status.stopped_signal()) {
+			return 128 + signal;
+		}
+	}
+	status.code().unwrap_or(1)
+}
+
 /// Substitutes environment variables into the new ones.
 #[must_use]
 pub fn substitute_env_var(input: impl AsRef<str>) -> String {
 	let mut vec: Vec<String> = vec![];
 	let input = input.as_ref();
-	for s in input.split_whitespace() {
+	for s in input.split_inclusive(char::is_whitespace) {
 		let mut s = String::from(s);
 		let s_clone = s.clone();
 		if let Some(vars) = deacon_parse::parse_env_vars(&s_clone) {
 			for var in vars {
 				let var_representation = String::from("?") + var + "?";
-				let val = std::env::var(&var).unwrap_or(var_representation.clone());
+				let val = std::env::var(var).unwrap_or(var_representation.clone());
 				s = s.replace(
 					&var_representation,
 					val.as_str()
@@ -62,10 +443,19 @@ pub fn substitute_env_var(input: impl AsRef<str>) -> String {
 		}
 		vec.push(s);
 	}
-	vec.join(" ")
+	vec.concat()
 }
 
 #[test]
 fn test() {
-    println!("{:?}", shell_words::split(r#"C:\s \" \\"#).unwrap());
-}
\ No newline at end of file
+    let words = deacon_parse::lexer::tokenize(r#"C:\s \" \\ "a  b""#).unwrap();
+    let words = words.iter().map(|f| expand_word(f.text, &Scope::new())).collect::<Vec<String>>();
+    assert_eq!(words, vec![r"C:\s", "\"", r"\", "a  b"]);
+    // redirected paths are expanded like arguments.
+    let path = std::env::temp_dir().join("deacon-redirect.txt");
+    let mut scope = Scope::new();
+    scope.set("f", path.display());
+    let redirection = Redirection::open(&[Redirect::Stdout { path: "\"$f\"".to_string(), append: false }], &scope).unwrap();
+    assert!(redirection.stdout.is_some() && path.exists());
+    std::fs::remove_file(path).unwrap();
+}
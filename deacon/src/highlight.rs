@@ -0,0 +1,217 @@
+//! Syntax highlighting of the line being edited.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use ansi_term::Colour::*;
+use ansi_term::Style;
+use deacon_parse::lexer::{tokenize, Token, TokenKind};
+use crate::alias::get_alias;
+use crate::commands::is_builtin;
+use crate::util::find_executable;
+
+/// Highlights lines using the [lexer](deacon_parse::lexer).
+///
+/// The name of a command is coloured depending on whether it is a builtin, a function,
+/// an executable in the `PATH` or unknown. Strings, `?VAR?` substitutions, `$variables`
+/// and keywords such as `func`, `let` and `if` are coloured too.
+#[derive(Default)]
+pub struct DeaconHighlighter {
+	/// Whether each name has an executable in the `PATH`.
+	executables: RefCell<HashMap<String, bool>>
+}
+
+/// What a command name refers to.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CommandKind {
+	Builtin,
+	Function,
+	Executable,
+	Unknown
+}
+
+impl DeaconHighlighter {
+	/// Returns what the given command name refers to, following aliases.
+	pub fn command_kind(&self, name: &str, functions: &HashSet<String>) -> CommandKind {
+		if let Some(name) = name.strip_prefix('!') {
+			// the escape syntax skips builtins, functions and aliases.
+			return if self.is_executable(name) { CommandKind::Executable } else { CommandKind::Unknown };
+		}
+		if let Some(alias) = get_alias(name) {
+			let target = alias.split_whitespace().next().unwrap_or_default();
+			if target != name {
+				return self.command_kind(target, functions);
+			}
+		}
+		if is_builtin(name) || name == "clear" {
+			CommandKind::Builtin
+		} else if functions.contains(name) {
+			CommandKind::Function
+		} else if self.is_executable(name) {
+			CommandKind::Executable
+		} else {
+			CommandKind::Unknown
+		}
+	}
+
+	/// Returns whether the given name is an executable, either as a path or in the `PATH`.
+	/// Names looked up in the `PATH` are cached, since the line is highlighted on every key.
+	fn is_executable(&self, name: &str) -> bool {
+		if name.contains(['/', '\\']) {
+			return find_executable(name).is_some();
+		}
+		if let Some(known) = self.executables.borrow().get(name) {
+			return *known;
+		}
+		let found = find_executable(name).is_some();
+		self.executables.borrow_mut().insert(name.to_string(), found);
+		found
+	}
+
+	/// Highlights the line with ANSI escape codes, given the names of the functions of the session.
+	pub fn highlight(&self, line: &str, functions: &HashSet<String>) -> String {
+		let mut styles = vec![Style::new(); line.len()];
+		// highlight what can be tokenized; the rest of the line is in an unclosed quote.
+		let (tokens, unclosed) = match tokenize(line) {
+			Ok(tokens) => (tokens, line.len()),
+			Err(err) => (tokenize(&line[..err.span.start]).unwrap_or_default(), err.span.start)
+		};
+		styles[unclosed..].fill(Yellow.normal());
+		let mut expect_command = true;
+		let mut after_func = false;
+		for token in &tokens {
+			let range = token.span.clone();
+			if token.kind != TokenKind::Word {
+				styles[range].fill(Style::new().bold());
+				expect_command = token.kind != TokenKind::Redirect;
+				continue
+			}
+			if after_func {
+				let name = token.text.find('(').unwrap_or(token.text.len());
+				styles[range.start..range.start + name].fill(Blue.bold());
+				after_func = false;
+				continue
+			}
+			if token.text == "{" || token.text == "}" {
+				styles[range].fill(Style::new().bold());
+				// a block starts with a command, such as the block after the condition of an `if`.
+				expect_command = true;
+				continue
+			}
+			if !expect_command {
+				highlight_argument(token, &mut styles);
+				continue
+			}
+			match token.text {
+				// the condition of an `if` or a `while` starts with a command.
+				"if" | "else" | "while" => styles[range].fill(Purple.bold()),
+				"for" | "break" | "continue" => {
+					styles[range].fill(Purple.bold());
+					expect_command = false;
+				}
+				"func" | "let" | "export" => {
+					styles[range].fill(Purple.bold());
+					after_func = token.text == "func";
+					// `export` is followed by a function declaration.
+					expect_command = token.text == "export";
+				}
+				text if text.starts_with('$') => {
+					highlight_argument(token, &mut styles);
+					expect_command = false;
+				}
+				text => {
+					let style = match self.command_kind(&deacon_parse::lexer::unquote(text), functions) {
+						CommandKind::Builtin => Cyan.bold(),
+						CommandKind::Function => Blue.bold(),
+						CommandKind::Executable => Green.bold(),
+						CommandKind::Unknown => Red.bold()
+					};
+					styles[range].fill(style);
+					expect_command = false;
+				}
+			}
+		}
+		paint(line, &styles)
+	}
+}
+
+/// Highlights the strings and variables of an argument.
+fn highlight_argument(token: &Token, styles: &mut [Style]) {
+	let text = token.text;
+	let offset = token.span.start;
+	let mut quote = None;
+	let mut chars = text.char_indices().peekable();
+	while let Some((index, ch)) = chars.next() {
+		match (quote, ch) {
+			(None, '\'') | (None, '"') => {
+				quote = Some(ch);
+				styles[offset + index] = Yellow.normal();
+			}
+			(Some(q), c) if c == q => {
+				quote = None;
+				styles[offset + index] = Yellow.normal();
+			}
+			(Some('\''), _) => styles[offset + index] = Yellow.normal(),
+			(_, '\\') => {
+				let style = if quote.is_some() { Yellow.normal() } else { Style::new() };
+				let length = 1 + chars.next().map_or(0, |f| f.1.len_utf8());
+				styles[offset + index..offset + index + length].fill(style);
+			}
+			(_, '$') | (_, '?') => {
+				let rest = &text[index + 1..];
+				let length = rest.find(|c: char| !(c.is_alphabetic() || c == '_')).unwrap_or(rest.len());
+				let closed = ch == '$' || rest[length..].starts_with('?');
+				if length > 0 && closed {
+					let end = index + 1 + length + if ch == '?' { 1 } else { 0 };
+					let style = if ch == '$' { Cyan.normal() } else { RGB(255, 165, 0).normal() };
+					styles[offset + index..offset + end].fill(style);
+					while chars.peek().is_some_and(|f| f.0 < end) {
+						chars.next();
+					}
+				} else if quote.is_some() {
+					styles[offset + index] = Yellow.normal();
+				}
+			}
+			(Some(_), c) => styles[offset + index..offset + index + c.len_utf8()].fill(Yellow.normal()),
+			(None, _) => {}
+		}
+	}
+}
+
+/// Paints each character of the line with the style at its first byte.
+fn paint(line: &str, styles: &[Style]) -> String {
+	let mut output = String::new();
+	if line.is_empty() {
+		return output;
+	}
+	let mut start = 0;
+	for (index, _) in line.char_indices().skip(1).chain(std::iter::once((line.len(), ' '))) {
+		if index == line.len() || styles[index] != styles[start] {
+			output.push_str(&styles[start].paint(&line[start..index]).to_string());
+			start = index;
+		}
+	}
+	output
+}
+
+#[test]
+fn test() {
+	let highlighter = DeaconHighlighter::default();
+	let functions = HashSet::from(["greet".to_string()]);
+	assert_eq!(highlighter.command_kind("cd", &functions), CommandKind::Builtin);
+	assert_eq!(highlighter.command_kind("greet", &functions), CommandKind::Function);
+	assert_eq!(highlighter.command_kind("!dcinfo", &functions), CommandKind::Unknown);
+	assert_eq!(highlighter.command_kind("deacon-surely-does-not-exist", &functions), CommandKind::Unknown);
+	let line = r#"cd "a $b" ?HOME? | x 'c"#;
+	let highlighted = highlighter.highlight(line, &functions);
+	assert!(highlighted.starts_with(&Cyan.bold().paint("cd").to_string()));
+	assert!(highlighted.contains(&Cyan.paint("$b").to_string()));
+	assert!(highlighted.contains(&RGB(255, 165, 0).paint("?HOME?").to_string()));
+	assert!(highlighted.ends_with(&Yellow.paint("'c").to_string()));
+	assert_eq!(highlighter.highlight("", &functions), "");
+	let highlighted = highlighter.highlight("if not greet { cd }", &functions);
+	assert!(highlighted.starts_with(&Purple.bold().paint("if").to_string()));
+	assert!(highlighted.contains(&Cyan.bold().paint("not").to_string()));
+	assert!(highlighted.contains(&Cyan.bold().paint("cd").to_string()));
+	let highlighted = highlighter.highlight("while true { break }", &functions);
+	assert!(highlighted.ends_with(&format!("{} {}", Purple.bold().paint("break"), Style::new().bold().paint("}"))));
+}
@@ -0,0 +1,105 @@
+//! Autosuggestions from history.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use rustyline::Context;
+
+/// The most entries kept for hints; older entries are still suggested from the history file.
+const MAX_ENTRIES: usize = 1000;
+
+/// A line run in this session, or in an earlier one.
+#[derive(Debug, PartialEq)]
+struct HintEntry {
+	line: String,
+	/// The directory the line was run in.
+	cwd: PathBuf,
+	success: bool
+}
+
+/// Suggests the rest of the line from history, like fish does.
+///
+/// Recorded lines, from this session and from the hints file of earlier ones, are preferred if they
+/// were run in the current directory, and then if they succeeded. If none of them match, the history
+/// file is searched, without either preference.
+#[derive(Default)]
+pub struct DeaconHinter {
+	entries: Vec<HintEntry>
+}
+
+impl DeaconHinter {
+	/// Records that the line was run in the given directory with the given exit status.
+	pub fn record(&mut self, line: &str, cwd: &Path, status: i32) {
+		self.entries.retain(|f| f.line != line || f.cwd != cwd);
+		if self.entries.len() == MAX_ENTRIES {
+			self.entries.remove(0);
+		}
+		self.entries.push(HintEntry { line: line.to_string(), cwd: cwd.to_path_buf(), success: status == 0 });
+	}
+
+	/// Reads the lines recorded in earlier sessions from the hints file, before the ones of this session.
+	pub fn load(&mut self, path: &Path) -> io::Result<()> {
+		let text = std::fs::read_to_string(path)?;
+		let mut entries = text.lines().filter_map(parse_entry).collect::<Vec<HintEntry>>();
+		entries.append(&mut self.entries);
+		self.entries = entries;
+		Ok(())
+	}
+
+	/// Writes the recorded lines to the hints file, one per line, with their status and directory.
+	pub fn save(&self, path: &Path) -> io::Result<()> {
+		let text = self.entries.iter().filter_map(format_entry).collect::<String>();
+		std::fs::write(path, text)
+	}
+
+	/// Returns the rest of the suggested line, if the cursor is at the end of the line.
+	pub fn hint(&self, line: &str, pos: usize, ctx: &Context<'_>) -> Option<String> {
+		if line.trim().is_empty() || pos < line.len() {
+			return None;
+		}
+		let cwd = std::env::current_dir().ok();
+		let best = self.entries.iter()
+			.enumerate()
+			.filter(|(_, f)| f.line.len() > line.len() && f.line.starts_with(line))
+			// the newest entry wins ties.
+			.max_by_key(|(index, f)| (cwd.as_deref() == Some(f.cwd.as_path()), f.success, *index));
+		if let Some((_, entry)) = best {
+			return Some(entry.line[pos..].to_string());
+		}
+		ctx.history().iter()
+			.rev()
+			.find(|f| f.len() > line.len() && f.starts_with(line))
+			.map(|f| f[pos..].to_string())
+	}
+}
+
+/// Formats the entry as a line of the hints file: `ok` or `failed`, the directory and the line, separated by tabs.
+/// Entries which would not fit on one line, or whose directory contains a tab, are left out.
+fn format_entry(entry: &HintEntry) -> Option<String> {
+	let cwd = entry.cwd.to_str()?;
+	if cwd.contains(['\t', '\n']) || entry.line.contains('\n') {
+		return None;
+	}
+	Some(format!("{}\t{}\t{}\n", if entry.success { "ok" } else { "failed" }, cwd, entry.line))
+}
+
+/// Parses a line of the hints file, skipping it if it is malformed.
+fn parse_entry(text: &str) -> Option<HintEntry> {
+	let mut fields = text.splitn(3, '\t');
+	let success = match fields.next()? {
+		"ok" => true,
+		"failed" => false,
+		_ => return None
+	};
+	let cwd = PathBuf::from(fields.next()?);
+	let line = fields.next()?.to_string();
+	Some(HintEntry { line, cwd, success })
+}
+
+#[test]
+fn test() {
+	let entry = HintEntry { line: "echo\tdone".to_string(), cwd: PathBuf::from("/tmp"), success: false };
+	assert_eq!(format_entry(&entry).as_deref(), Some("failed\t/tmp\techo\tdone\n"));
+	assert_eq!(parse_entry("failed\t/tmp\techo\tdone"), Some(entry));
+	assert_eq!(parse_entry("maybe\t/tmp\techo"), None);
+	assert_eq!(parse_entry("ok\t/tmp"), None);
+}
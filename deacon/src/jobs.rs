@@ -0,0 +1,508 @@
+//! Jobs, that is, pipelines started in the background or stopped in the foreground.
+//!
+//! Every job has a number, shown as `[n]`, which the `fg`, `bg`, `wait` and `kill` builtins take as `%n`.
+//! When a job finishes or stops in the background, it is reported before the next prompt.
+
+use std::cell::RefCell;
+use std::io;
+use std::io::Write;
+use std::process::{Child, ExitStatus};
+use ansi_term::Colour::*;
+use crate::env::exit_code;
+use crate::signals;
+use crate::signals::Foreground;
+
+thread_local! {
+	static JOBS: RefCell<Vec<Job>> = const { RefCell::new(vec![]) };
+}
+
+/// The state of a job, or of one of its processes.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum JobState {
+	Running,
+	/// Stopped, with the exit code the shell reports for it.
+	Stopped(i32),
+	/// Finished, with its exit code.
+	Done(i32)
+}
+
+struct Process {
+	child: Child,
+	state: JobState
+}
+
+struct Job {
+	id: usize,
+	/// The process group of the job, if job control was enabled when it started.
+	group: Option<u32>,
+	processes: Vec<Process>,
+	command: String,
+	/// The state of the job when it was last reported.
+	reported: Option<JobState>
+}
+
+impl Job {
+	fn new(children: Vec<Child>, command: String) -> Job {
+		Job {
+			id: 0,
+			group: children.first().map(|f| f.id()).filter(|_| signals::is_enabled()),
+			processes: children.into_iter().map(|child| Process { child, state: JobState::Running }).collect(),
+			command,
+			reported: None
+		}
+	}
+
+	/// Returns the state of the job: stopped if any process is stopped, running if any process
+	/// is running, and otherwise done with the exit code of its last process.
+	fn state(&self) -> JobState {
+		let states = self.processes.iter().map(|f| f.state);
+		if let Some(stopped) = states.clone().find(|f| matches!(f, JobState::Stopped(_))) {
+			stopped
+		} else if states.clone().any(|f| f == JobState::Running) {
+			JobState::Running
+		} else {
+			self.processes.last().map_or(JobState::Done(0), |f| f.state)
+		}
+	}
+
+	/// Updates the state of every process which has not finished, without blocking.
+	fn update(&mut self) {
+		for process in &mut self.processes {
+			if !matches!(process.state, JobState::Done(_)) {
+				update(process, false);
+			}
+		}
+	}
+
+	/// Sends the signal to every process of the job.
+	#[cfg(unix)]
+	fn signal(&mut self, signal: i32) -> io::Result<()> {
+		let result = match self.group {
+			Some(group) => unsafe { libc::kill(-(group as i32), signal) },
+			None => self.processes.iter()
+				.filter(|f| !matches!(f.state, JobState::Done(_)))
+				.map(|f| unsafe { libc::kill(f.child.id() as i32, signal) })
+				.min()
+				.unwrap_or(0)
+		};
+		if result == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+	}
+
+	/// Stops the processes of the job; no other signal can be sent on this platform.
+	#[cfg(not(unix))]
+	fn signal(&mut self, _: i32) -> io::Result<()> {
+		self.processes.iter_mut().try_for_each(|f| f.child.kill())
+	}
+
+	/// Continues the job if it is stopped.
+	fn resume(&mut self) -> io::Result<()> {
+		if !matches!(self.state(), JobState::Stopped(_)) {
+			return Ok(());
+		}
+		#[cfg(unix)]
+		self.signal(libc::SIGCONT)?;
+		for process in &mut self.processes {
+			if matches!(process.state, JobState::Stopped(_)) {
+				process.state = JobState::Running;
+			}
+		}
+		Ok(())
+	}
+
+	/// Gives the job the terminal and waits until it finishes or stops.
+	fn wait_foreground(&mut self) -> JobState {
+		let _foreground = self.processes.first().map(|f| Foreground::new(self.group.unwrap_or(f.child.id())));
+		for process in &mut self.processes {
+			while process.state == JobState::Running {
+				update(process, true);
+			}
+		}
+		self.state()
+	}
+
+	fn describe(&self, state: JobState) -> String {
+		let state = match state {
+			JobState::Running => Green.paint("Running").to_string(),
+			JobState::Stopped(_) => Yellow.paint("Stopped").to_string(),
+			JobState::Done(0) => Green.paint("Done").to_string(),
+			JobState::Done(code) => Red.paint(format!("Exit {}", code)).to_string()
+		};
+		// the state is padded as if it was not coloured.
+		let width = 10 + state.len() - strip_len(&state);
+		format!("[{}] {:<width$} {}", self.id, state, self.command, width = width)
+	}
+}
+
+/// Returns the length of the text without ANSI escape codes.
+fn strip_len(text: &str) -> usize {
+	let mut length = 0;
+	let mut escape = false;
+	for ch in text.chars() {
+		match (escape, ch) {
+			(false, '\x1B') => escape = true,
+			(true, 'm') => escape = false,
+			(false, _) => length += 1,
+			_ => {}
+		}
+	}
+	length
+}
+
+/// Updates the state of the process, blocking until it changes if `block` is true.
+#[cfg(unix)]
+fn update(process: &mut Process, block: bool) {
+	use std::os::unix::process::ExitStatusExt;
+	let pid = process.child.id() as i32;
+	let options = libc::WUNTRACED | libc::WCONTINUED | if block { 0 } else { libc::WNOHANG };
+	let mut status = 0;
+	loop {
+		match unsafe { libc::waitpid(pid, &mut status, options) } {
+			0 => return,
+			result if result == pid => break,
+			_ if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => continue,
+			_ => {
+				// the process cannot be waited for anymore, so it is gone.
+				process.state = JobState::Done(1);
+				return
+			}
+		}
+	}
+	let status = ExitStatus::from_raw(status);
+	process.state = if status.stopped_signal().is_some() {
+		JobState::Stopped(exit_code(status))
+	} else if status.continued() {
+		JobState::Running
+	} else {
+		JobState::Done(exit_code(status))
+	};
+}
+
+/// Blocks until a running process of the given jobs, or of every job, exits.
+#[cfg(not(unix))]
+fn wait_any(ids: &[Option<usize>]) {
+	JOBS.with(|jobs| {
+		let mut jobs = jobs.borrow_mut();
+		let process = jobs.iter_mut()
+			.filter(|f| ids.is_empty() || ids.contains(&Some(f.id)))
+			.flat_map(|f| f.processes.iter_mut())
+			.find(|f| f.state == JobState::Running);
+		if let Some(process) = process {
+			update(process, true);
+		}
+	});
+}
+
+/// Updates the state of the process, blocking until it exits if `block` is true.
+#[cfg(not(unix))]
+fn update(process: &mut Process, block: bool) {
+	let status: Option<ExitStatus> = if block {
+		process.child.wait().ok()
+	} else {
+		process.child.try_wait().ok().flatten()
+	};
+	match status {
+		Some(status) => process.state = JobState::Done(exit_code(status)),
+		None if block => process.state = JobState::Done(1),
+		None => {}
+	}
+}
+
+/// Adds the job to the table, giving it the lowest number above every other job's.
+fn insert(mut job: Job) -> usize {
+	JOBS.with(|jobs| {
+		let mut jobs = jobs.borrow_mut();
+		if job.id == 0 {
+			job.id = jobs.iter().map(|f| f.id).max().unwrap_or(0) + 1;
+		}
+		let id = job.id;
+		let index = jobs.iter().position(|f| f.id > id).unwrap_or(jobs.len());
+		jobs.insert(index, job);
+		id
+	})
+}
+
+/// Removes the job with the given number from the table. Without a number, the newest job is removed.
+fn take(id: Option<usize>) -> Option<Job> {
+	JOBS.with(|jobs| {
+		let mut jobs = jobs.borrow_mut();
+		let index = match id {
+			Some(id) => jobs.iter().position(|f| f.id == id)?,
+			None => jobs.len().checked_sub(1)?
+		};
+		Some(jobs.remove(index))
+	})
+}
+
+/// Starts tracking a pipeline running in the background. If job control is enabled, its number is printed.
+pub fn add_background(children: Vec<Child>, command: String) {
+	let pid = children.last().map_or(0, |f| f.id());
+	let mut job = Job::new(children, command);
+	job.reported = Some(JobState::Running);
+	let id = insert(job);
+	if signals::is_enabled() {
+		eprintln!("[{}] {}", id, pid);
+	}
+}
+
+/// Waits for a pipeline running in the foreground to finish or stop. A stopped pipeline becomes a job.
+pub fn wait_foreground(children: Vec<Child>, command: String) -> JobState {
+	let mut job = Job::new(children, command);
+	let state = job.wait_foreground();
+	if let JobState::Stopped(_) = state {
+		job.reported = Some(state);
+		let id = insert(job);
+		let line = JOBS.with(|jobs| jobs.borrow().iter().find(|f| f.id == id).map(|f| f.describe(state)));
+		eprintln!("\n{}", line.unwrap_or_default());
+	}
+	state
+}
+
+/// Reports every job which finished or stopped since it was last reported, and forgets finished jobs.
+/// As with the job number of a new background job, nothing is reported without job control,
+/// so that scripts and `-c` commands only print their own output.
+pub fn notify() {
+	report(signals::is_enabled(), &mut io::stderr());
+}
+
+/// Updates every job, writing the changes of [notify] to the output if `print` is set.
+fn report(print: bool, output: &mut dyn Write) {
+	JOBS.with(|jobs| {
+		let mut jobs = jobs.borrow_mut();
+		for job in jobs.iter_mut() {
+			job.update();
+			let state = job.state();
+			if job.reported != Some(state) {
+				job.reported = Some(state);
+				if print {
+					let _ = writeln!(output, "{}", job.describe(state));
+				}
+			}
+		}
+		jobs.retain(|f| !matches!(f.state(), JobState::Done(_)));
+	});
+}
+
+/// Parses a job number, written as `%n` or `n`. `%`, `%%` and `%+` refer to the newest job.
+fn parse_job(arg: &str) -> Result<Option<usize>, String> {
+	match arg {
+		"%" | "%%" | "%+" => Ok(None),
+		_ => arg.strip_prefix('%').unwrap_or(arg).parse::<usize>()
+			.map(Some)
+			.map_err(|_| format!("\"{}\" is not a job number", arg))
+	}
+}
+
+/// Takes the job given by the first argument out of the table, or the newest job without arguments.
+fn take_job(builtin: &str, args: &[String], stderr: &mut dyn Write) -> Option<Job> {
+	let id = match args.first().map(|f| parse_job(f)).transpose() {
+		Ok(id) => id.flatten(),
+		Err(err) => {
+			let _ = writeln!(stderr, "{}", Red.paint(format!("{}: {}", builtin, err)));
+			return None
+		}
+	};
+	let job = take(id);
+	if job.is_none() {
+		let _ = writeln!(stderr, "{}", Red.paint(format!("{}: no such job", builtin)));
+	}
+	job
+}
+
+/// The `jobs` builtin, which lists the jobs.
+pub fn list_jobs(stdout: &mut dyn Write) -> i32 {
+	JOBS.with(|jobs| {
+		let mut jobs = jobs.borrow_mut();
+		for job in jobs.iter_mut() {
+			job.update();
+			let state = job.state();
+			job.reported = Some(state);
+			let _ = writeln!(stdout, "{}", job.describe(state));
+		}
+		jobs.retain(|f| !matches!(f.state(), JobState::Done(_)));
+	});
+	0
+}
+
+/// The `fg` builtin, which continues a job in the foreground and waits for it.
+/// The exit code is the job's.
+pub fn foreground(args: &[String], stdout: &mut dyn Write, stderr: &mut dyn Write) -> i32 {
+	let Some(mut job) = take_job("fg", args, stderr) else {
+		return 1
+	};
+	let _ = writeln!(stdout, "{}", job.command);
+	let _ = stdout.flush();
+	if let Err(err) = job.resume() {
+		let _ = writeln!(stderr, "{}", Red.paint(format!("fg: failed to continue the job: {}", err)));
+	}
+	match job.wait_foreground() {
+		state @ JobState::Stopped(code) => {
+			job.reported = Some(state);
+			let _ = writeln!(stderr, "\n{}", job.describe(state));
+			insert(job);
+			code
+		}
+		JobState::Done(code) => code,
+		JobState::Running => 0
+	}
+}
+
+/// The `bg` builtin, which continues a stopped job in the background.
+pub fn background(args: &[String], stdout: &mut dyn Write, stderr: &mut dyn Write) -> i32 {
+	let Some(mut job) = take_job("bg", args, stderr) else {
+		return 1
+	};
+	let code = match job.resume() {
+		Ok(()) => {
+			job.reported = Some(JobState::Running);
+			let _ = writeln!(stdout, "[{}] {} &", job.id, job.command);
+			0
+		}
+		Err(err) => {
+			let _ = writeln!(stderr, "{}", Red.paint(format!("bg: failed to continue the job: {}", err)));
+			1
+		}
+	};
+	insert(job);
+	code
+}
+
+/// The `wait` builtin, which waits until the given jobs, or every job, are no longer running.
+/// Ctrl-C stops waiting. The exit code is the last given job's, or 0 without jobs.
+pub fn wait_jobs(args: &[String], stderr: &mut dyn Write) -> i32 {
+	let ids = match args.iter().map(|f| parse_job(f)).collect::<Result<Vec<Option<usize>>, String>>() {
+		Ok(ids) => ids,
+		Err(err) => {
+			let _ = writeln!(stderr, "{}", Red.paint(format!("wait: {}", err)));
+			return 2
+		}
+	};
+	let newest = JOBS.with(|jobs| jobs.borrow().last().map(|f| f.id));
+	let ids = ids.into_iter().map(|f| f.or(newest)).collect::<Vec<Option<usize>>>();
+	#[cfg(unix)]
+	let waiting = signals::Waiting::new();
+	signals::take_interrupt();
+	loop {
+		let running = JOBS.with(|jobs| {
+			let mut jobs = jobs.borrow_mut();
+			let mut running = false;
+			for job in jobs.iter_mut().filter(|f| ids.is_empty() || ids.contains(&Some(f.id))) {
+				job.update();
+				running |= job.state() == JobState::Running;
+			}
+			running
+		});
+		if !running {
+			break
+		}
+		if signals::take_interrupt() {
+			return 130;
+		}
+		// a child which changes state from now on, or Ctrl-C, wakes the shell up.
+		#[cfg(unix)]
+		waiting.block();
+		#[cfg(not(unix))]
+		wait_any(&ids);
+	}
+	let state = ids.last().map(|id| JOBS.with(|jobs| jobs.borrow().iter().find(|f| Some(f.id) == *id).map(Job::state)));
+	notify();
+	match state {
+		Some(Some(JobState::Done(code) | JobState::Stopped(code))) => code,
+		Some(_) => {
+			let _ = writeln!(stderr, "{}", Red.paint("wait: no such job"));
+			127
+		}
+		None => 0
+	}
+}
+
+/// The `kill` builtin, which sends a signal to jobs and processes: `kill [-SIGNAL] (%n | pid)...`.
+/// The signal is a name such as `KILL` or `SIGKILL`, or a number, and defaults to `TERM`.
+pub fn kill(args: &[String], stderr: &mut dyn Write) -> i32 {
+	let (signal, targets) = match args.split_first() {
+		Some((first, rest)) if first.starts_with('-') => match parse_signal(&first[1..]) {
+			Some(signal) => (signal, rest),
+			None => {
+				let _ = writeln!(stderr, "{}", Red.paint(format!("kill: unknown signal \"{}\"", &first[1..])));
+				return 2
+			}
+		},
+		_ => (DEFAULT_SIGNAL, args)
+	};
+	if targets.is_empty() {
+		let _ = writeln!(stderr, "{}", Red.paint("kill: expected a job or a process ID"));
+		return 2
+	}
+	let mut code = 0;
+	for target in targets {
+		if let Err(err) = kill_target(target, signal) {
+			let _ = writeln!(stderr, "{}", Red.paint(format!("kill: {}: {}", target, err)));
+			code = 1;
+		}
+	}
+	code
+}
+
+#[cfg(unix)]
+const DEFAULT_SIGNAL: i32 = libc::SIGTERM;
+#[cfg(not(unix))]
+const DEFAULT_SIGNAL: i32 = 15;
+
+fn kill_target(target: &str, signal: i32) -> Result<(), String> {
+	if target.starts_with('%') {
+		let id = parse_job(target)?;
+		let mut job = take(id).ok_or("no such job")?;
+		job.update();
+		let mut result = job.signal(signal).map_err(|f| f.to_string());
+		// a stopped job only handles the signal once it continues.
+		#[cfg(unix)]
+		if result.is_ok() && signal != libc::SIGKILL && signal != libc::SIGSTOP {
+			result = job.resume().map_err(|f| f.to_string());
+		}
+		insert(job);
+		return result;
+	}
+	let pid = target.parse::<i32>().map_err(|_| "not a job or a process ID".to_string())?;
+	#[cfg(unix)]
+	{
+		if unsafe { libc::kill(pid, signal) } != 0 {
+			return Err(io::Error::last_os_error().to_string());
+		}
+		Ok(())
+	}
+	#[cfg(not(unix))]
+	{
+		let _ = (pid, signal);
+		Err("only jobs can be killed on this platform".to_string())
+	}
+}
+
+/// Parses the name or number of a signal.
+fn parse_signal(name: &str) -> Option<i32> {
+	if let Ok(number) = name.parse::<i32>() {
+		return Some(number);
+	}
+	let name = name.to_ascii_uppercase();
+	let name = name.strip_prefix("SIG").unwrap_or(&name);
+	#[cfg(unix)]
+	let signals = [
+		("HUP", libc::SIGHUP), ("INT", libc::SIGINT), ("QUIT", libc::SIGQUIT), ("KILL", libc::SIGKILL),
+		("USR1", libc::SIGUSR1), ("USR2", libc::SIGUSR2), ("TERM", libc::SIGTERM), ("CONT", libc::SIGCONT),
+		("STOP", libc::SIGSTOP), ("TSTP", libc::SIGTSTP)
+	];
+	#[cfg(not(unix))]
+	let signals = [("KILL", 9), ("TERM", 15)];
+	signals.iter().find(|f| f.0 == name).map(|f| f.1)
+}
+
+#[test]
+fn test() {
+	// a job without processes is done as soon as it is checked.
+	let mut output = vec![];
+	insert(Job::new(vec![], "first".to_string()));
+	report(false, &mut output);
+	assert!(output.is_empty());
+	assert!(JOBS.with(|jobs| jobs.borrow().is_empty()));
+	insert(Job::new(vec![], "second".to_string()));
+	report(true, &mut output);
+	assert_eq!(String::from_utf8(output).unwrap(), format!("[1] {}       second\n", Green.paint("Done")));
+}
@@ -1,30 +1,45 @@
-#![feature(let_chains)]
-#![feature(exact_size_is_empty)]
-#![feature(iter_intersperse)]
-
+mod alias;
+mod cli;
 mod commands;
+mod completion;
+mod config;
 mod integrations;
 mod util;
 mod env;
+mod highlight;
+mod hint;
+mod jobs;
+mod prompt;
+mod signals;
 
 use std::borrow::Cow;
 use std::borrow::Cow::{Borrowed, Owned};
+use std::collections::HashSet;
+use std::io;
+use std::io::{IsTerminal, Read};
 use std::path::Path;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use ansi_term::Colour::*;
 use ariadne::{Label, ReportKind, Source};
 use chrono::*;
 use guess_host_triple::guess_host_triple;
 use rustyline::*;
-use rustyline::completion::FilenameCompleter;
+use rustyline::completion::{Completer, Pair};
 use rustyline::config::Configurer;
 use rustyline::error::ReadlineError;
 use rustyline::highlight::Highlighter;
-use rustyline::validate::MatchingBracketValidator;
+use rustyline::hint::Hinter;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
 use rustyline_derive::*;
-use crate::commands::resolve_function;
-use crate::env::execute_process;
-use crate::util::print_prompt;
+use deacon_engine::interpreter::Interpreter;
+use deacon_engine::{report_parse_error, run_source};
+use crate::cli::{Mode, Options, USAGE};
+use crate::completion::DeaconCompleter;
+use crate::config::CONFIG;
+use crate::env::Shell;
+use crate::highlight::DeaconHighlighter;
+use crate::hint::DeaconHinter;
+use crate::prompt::{render, PromptContext};
 
 const ASCII_LOGO: &str =
 r#"    ____
@@ -33,18 +48,45 @@ r#"    ____
  / /_/ /  __/ /_/ / /__/ /_/ / / / /
 /_____/\___/\__,_/\___/\____/_/ /_/"#;
 
-#[derive(Helper, Completer, Hinter, Validator)]
+#[derive(Helper)]
 struct MyHelper {
-    #[rustyline(Completer)]
-    completer: FilenameCompleter,
-    #[rustyline(Validator)]
-    validator: MatchingBracketValidator, // replace soon with deacon engine validator
+    completer: DeaconCompleter,
+    highlighter: DeaconHighlighter,
+    hinter: DeaconHinter,
+    /// The names of the functions of the session.
+    functions: HashSet<String>,
     colored_prompt: String,
 }
 
+impl Completer for MyHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, ctx: &Context<'_>) -> Result<(usize, Vec<Pair>)> {
+        self.completer.complete(line, pos, ctx, &self.functions)
+    }
+}
+
+impl Hinter for MyHelper {
+    type Hint = String;
+
+    fn hint(&self, line: &str, pos: usize, ctx: &Context<'_>) -> Option<String> {
+        self.hinter.hint(line, pos, ctx)
+    }
+}
+
+impl Validator for MyHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> Result<ValidationResult> {
+        // keep reading lines until every function declaration, `if` statement and block is closed.
+        match deacon_parse::parse_script(ctx.input()) {
+            Err(err) if err.message == "unclosed `{`" || err.message == "expected the body of the function" => Ok(ValidationResult::Incomplete),
+            _ => Ok(ValidationResult::Valid(None)),
+        }
+    }
+}
+
 impl Highlighter for MyHelper {
     fn highlight<'l>(&self, line: &'l str, _: usize) -> Cow<'l, str> {
-        Owned(line.to_string())
+        Owned(self.highlighter.highlight(line, &self.functions))
     }
 
     fn highlight_prompt<'b, 's: 'b, 'p: 'b>(
@@ -64,36 +106,59 @@ impl Highlighter for MyHelper {
     }
 
     fn highlight_char(&self, _: &str, _: usize) -> bool {
-        false
+        // any character can change how the rest of the line is highlighted, such as a quote.
+        true
     }
 }
 
 fn main() -> Result<()> {
     #[cfg(windows)]
     ansi_term::enable_ansi_support().unwrap();
-    println!("{}\n", Yellow.bold().paint(ASCII_LOGO));
-    println!("{} [{} {} on {}]",
-             Yellow.bold().paint("Deacon Shell"),
-             Cyan.paint(env!("CARGO_PKG_VERSION")),
-             {
-                if !env!("CARGO_PKG_VERSION").starts_with("0.") {
-                    Green.bold().paint("stable")
-                } else {
-                    Red.bold().paint("unstable")
-                }
-            },
-            Cyan.bold().paint(guess_host_triple().unwrap_or("unknown"))
-    );
-    println!("{}", RGB(255, 165, 0).bold().paint(format!("Current PID: {}{}", std::process::id(), {
-        if Local::now().month() == 12 {
-            " | Merry Christmas!"
-        } else if Local::now().month() == 10 {
-            " | Happy Halloween!"
-        } else {
-            ""
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n\n{}", Red.paint(err), USAGE);
+            std::process::exit(2);
         }
-    })));
-
+    };
+    signals::install_handler();
+    match options.mode {
+        Mode::Help => {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+        Mode::Version => {
+            println!("deacon {}", env!("CARGO_PKG_VERSION"));
+            return Ok(());
+        }
+        Mode::Run(path) => {
+            // this process is the subshell, so `export` has no effect on the shell that started it.
+            if !Path::new(&path).is_file() {
+                eprintln!("{}", Red.paint(format!("Failed to run \"{}\": no such script", path)));
+                std::process::exit(1);
+            }
+            let mut interpreter = Interpreter::new(Shell);
+            std::process::exit(deacon_engine::run_script(&path, &mut interpreter).unwrap_or(2));
+        }
+        Mode::Command(command) => {
+            let mut interpreter = Interpreter::new(Shell);
+            std::process::exit(run_source("command", &command, &mut interpreter).unwrap_or(2));
+        }
+        Mode::Interactive if !io::stdin().is_terminal() => {
+            let mut input = String::new();
+            if let Err(err) = io::stdin().read_to_string(&mut input) {
+                eprintln!("{}", Red.paint(format!("Failed to read the standard input: {}", err)));
+                std::process::exit(1);
+            }
+            let mut interpreter = Interpreter::new(Shell);
+            std::process::exit(run_source("stdin", &input, &mut interpreter).unwrap_or(2));
+        }
+        Mode::Interactive => {}
+    }
+    signals::enable_job_control();
+    if !options.quiet {
+        print_banner();
+    }
     std::panic::set_hook(Box::new(|panic_info| {
         use ariadne::*;
         let location = panic_info.location().unwrap(); // current version always returns `Some`
@@ -117,12 +182,19 @@ fn main() -> Result<()> {
     )?;
     rl.set_color_mode(ColorMode::Enabled);
     rl.set_helper(Some(MyHelper {
-        completer: FilenameCompleter::new(),
-        validator: MatchingBracketValidator::new(),
-        colored_prompt: "$ ".to_string(),
+        completer: DeaconCompleter::default(),
+        highlighter: DeaconHighlighter::default(),
+        hinter: DeaconHinter::default(),
+        functions: HashSet::new(),
+        colored_prompt: String::new(),
     }));
     let history_path = Path::new(".devcon-history.txt");
-    if rl.load_history(history_path).is_ok() {
+    // the directory and the exit status of each line, for hints.
+    let hints_path = Path::new(".devcon-hints.txt");
+    if !options.quiet && let Some(helper) = rl.helper_mut() {
+        let _ = helper.hinter.load(hints_path);
+    }
+    if !options.quiet && rl.load_history(history_path).is_ok() {
         let md = history_path.metadata().expect("able to get metadata");
         match md.modified() {
             Ok(time) => {
@@ -139,7 +211,7 @@ fn main() -> Result<()> {
                             }
                         },
                     };
-                    NaiveDateTime::from_timestamp_opt(sec, nsec).unwrap().and_local_timezone(Local).earliest().unwrap()
+                    DateTime::from_timestamp(sec, nsec).unwrap().with_timezone(&Local)
                 }
                 println!("Restored previous session on {}.", Blue.bold().paint(system_time_to_date_time(time).format("%d %B %Y %I:%M %p").to_string()));
             }
@@ -148,26 +220,28 @@ fn main() -> Result<()> {
             }
         }
     }
-    println!("For help, type `help` and hit enter.\n");
+    if !options.quiet {
+        println!("For help, type `help` and hit enter.\n");
+    }
+    let mut interpreter = Interpreter::new(Shell);
+    let mut context = PromptContext::default();
+    let mut exit_code = 0;
     loop {
-        print_prompt();
-        let readline = rl.readline("  ");
+        jobs::notify();
+        println!("{}", render(&CONFIG.upper_prompt, &CONFIG, &context, true));
+        // the prompt given to rustyline must not contain escape codes, so that its width is right.
+        let lower_prompt = render(&CONFIG.lower_prompt, &CONFIG, &context, false);
+        if let Some(helper) = rl.helper_mut() {
+            helper.colored_prompt = render(&CONFIG.lower_prompt, &CONFIG, &context, true);
+        }
+        let readline = rl.readline(&lower_prompt);
         match readline {
             Ok(line) => {
                 let line = line.trim();
-                if is_blank(&line) {
+                if is_blank(line) {
                     println!();
                     continue;
                 } else {
-                    let line = env::substitute_env_var(line);
-                    let line = line.split_whitespace()
-                        .map(|f| if f.trim().starts_with("\"") || f.trim().starts_with("\'") {
-                            f.to_string()
-                        } else {
-                            f.replace("~", &*dirs::home_dir().map(|f| f.to_string_lossy().to_string()).unwrap_or(String::from("~")))
-                        }).intersperse(" ".parse().unwrap())
-                        .collect::<String>();
-                    let line: &str = &line;
                     if !line.trim().is_empty() && line.split_whitespace().next().unwrap().trim() == "!" {
                         ariadne::Report::build(ReportKind::Error, (), line.find("!").unwrap())
                             .with_code(1)
@@ -180,31 +254,31 @@ fn main() -> Result<()> {
                             .unwrap_or(());
                         continue
                     }
-                    if !line.starts_with("exit") {
-                        rl.add_history_entry(line);
-                        if line.starts_with("clear") {
-                            print!("\x1B[2J\x1B[1;1H");
-                        } else if !resolve_function(&line) {
-                            // execute a process
-                            if let Some((_command, mut child)) = execute_process(line) {
-                                // let pid = child.id();
-                                while let Ok(None) = child.try_wait() {
-                                    // if rx.try_recv().is_ok() {
-                                    //     #[cfg(windows)]
-                                    //     unsafe {
-                                    //         use windows::Win32::System::Console::*;
-                                    //         AttachConsole(pid);
-                                    //         GenerateConsoleCtrlEvent(CTRL_C_EVENT, pid);
-                                    //     }
-                                    // }
-                                }
-                            }
-                            println!();
-                        } else {
+                    rl.add_history_entry(line);
+                    let script = match deacon_parse::parse_script(line) {
+                        Ok(script) => script,
+                        Err(err) => {
+                            report_parse_error("input", line, &err);
                             println!();
+                            continue
                         }
+                    };
+                    if line.starts_with("clear") {
+                        print!("\x1B[2J\x1B[1;1H");
                     } else {
-                        break
+                        let cwd = std::env::current_dir().unwrap_or_default();
+                        let start = Instant::now();
+                        context.status = interpreter.run(&script);
+                        context.duration = Some(start.elapsed());
+                        if let Some(code) = interpreter.exit_code() {
+                            exit_code = code;
+                            break
+                        }
+                        if let Some(helper) = rl.helper_mut() {
+                            helper.functions = interpreter.functions().map(|f| f.name.clone()).collect();
+                            helper.hinter.record(line, &cwd, context.status);
+                        }
+                        println!();
                     }
                 }
             },
@@ -213,6 +287,7 @@ fn main() -> Result<()> {
             },
             Err(ReadlineError::Eof) => {
                 println!("CTRL-D");
+                exit_code = context.status;
                 break
             },
             Err(err) => {
@@ -221,7 +296,39 @@ fn main() -> Result<()> {
             }
         }
     }
-    rl.save_history(".devcon-history.txt")
+    if !options.quiet {
+        rl.save_history(history_path)?;
+        if let Some(helper) = rl.helper() {
+            helper.hinter.save(hints_path)?;
+        }
+    }
+    std::process::exit(exit_code)
+}
+
+/// Prints the logo, the version and the PID of the shell.
+fn print_banner() {
+    println!("{}\n", Yellow.bold().paint(ASCII_LOGO));
+    println!("{} [{} {} on {}]",
+             Yellow.bold().paint("Deacon Shell"),
+             Cyan.paint(env!("CARGO_PKG_VERSION")),
+             {
+                if !env!("CARGO_PKG_VERSION").starts_with("0.") {
+                    Green.bold().paint("stable")
+                } else {
+                    Red.bold().paint("unstable")
+                }
+            },
+            Cyan.bold().paint(guess_host_triple().unwrap_or("unknown"))
+    );
+    println!("{}", RGB(255, 165, 0).bold().paint(format!("Current PID: {}{}", std::process::id(), {
+        if Local::now().month() == 12 {
+            " | Merry Christmas!"
+        } else if Local::now().month() == 10 {
+            " | Happy Halloween!"
+        } else {
+            ""
+        }
+    })));
 }
 
 pub fn is_blank(input: impl AsRef<str>) -> bool {
@@ -0,0 +1,167 @@
+//! Prompt templates.
+//!
+//! The `upper_prompt` and `lower_prompt` of the configuration are templates, where every `{placeholder}`
+//! is replaced when the prompt is shown:
+//!
+//! | Placeholder         | Replaced with                                                           |
+//! |---------------------|-------------------------------------------------------------------------|
+//! | `{user}`            | The name of the current user.                                           |
+//! | `{host}`            | The name of this computer.                                              |
+//! | `{cwd}`             | The current directory.                                                  |
+//! | `{time}`            | The current time, as `%H:%M:%S`. `{time:FORMAT}` uses a custom format.  |
+//! | `{status}`          | The exit status of the last command, or nothing if it succeeded.        |
+//! | `{duration}`        | How long the last command took, or nothing if no command has run yet.   |
+//! | `{rust}`            | The Rust integration segment, such as `[crate "deacon"]`.               |
+//! | `{git}`             | The Git integration segment, such as `[branch "main"]`.                 |
+//!
+//! Colours are set with the directives `{black}`, `{red}`, `{green}`, `{yellow}`, `{blue}`, `{purple}`,
+//! `{cyan}`, `{white}` and `{rgb:R,G,B}`, and styles with `{bold}`, `{dimmed}`, `{italic}` and `{underline}`.
+//! `{reset}` resets both. `{{` and `}}` are literal braces, and unknown placeholders are kept as-is.
+
+use std::fmt::Write;
+use std::time::Duration;
+use ansi_term::Colour::*;
+use ansi_term::Style;
+use chrono::Local;
+use deacon_config::DeaconConfig;
+use crate::integrations::git::{get_integration, get_nearest_git_repository};
+use crate::integrations::rust::*;
+
+/// What the prompt shows about the previous command.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PromptContext {
+	/// The exit status of the last command.
+	pub status: i32,
+	/// How long the last command took, if a command has run.
+	pub duration: Option<Duration>
+}
+
+/// Renders the template. If `ansi` is false, colour directives are removed instead of
+/// being replaced with ANSI escape codes.
+pub fn render(template: &str, config: &DeaconConfig, context: &PromptContext, ansi: bool) -> String {
+	let mut output = String::new();
+	let mut rest = template;
+	while let Some(index) = rest.find(['{', '}']) {
+		output.push_str(&rest[..index]);
+		let tail = &rest[index..];
+		if tail.starts_with("{{") || tail.starts_with("}}") {
+			output.push_str(&tail[..1]);
+			rest = &tail[2..];
+			continue
+		}
+		let end = match tail.find('}') {
+			Some(end) if tail.starts_with('{') => end,
+			_ => {
+				// a lone brace.
+				output.push_str(&tail[..1]);
+				rest = &tail[1..];
+				continue
+			}
+		};
+		let placeholder = &tail[1..end];
+		match render_placeholder(placeholder, config, context) {
+			Some(Segment::Text(text)) => output.push_str(&text),
+			Some(Segment::Style(style)) if ansi => output.push_str(&style),
+			Some(Segment::Style(_)) => {}
+			None => output.push_str(&tail[..=end])
+		}
+		rest = &tail[end + 1..];
+	}
+	output.push_str(rest);
+	output
+}
+
+enum Segment {
+	Text(String),
+	Style(String)
+}
+
+fn render_placeholder(placeholder: &str, config: &DeaconConfig, context: &PromptContext) -> Option<Segment> {
+	let (name, argument) = match placeholder.split_once(':') {
+		Some((name, argument)) => (name, Some(argument)),
+		None => (placeholder, None)
+	};
+	let style = |style: Style| Some(Segment::Style(style.prefix().to_string()));
+	match (name, argument) {
+		("user", None) => Some(Segment::Text(whoami::username())),
+		("host", None) => Some(Segment::Text(whoami::fallible::hostname().unwrap_or_default())),
+		("cwd", None) => Some(Segment::Text(std::env::current_dir().map_or(String::new(), |f| f.to_string_lossy().trim_end().to_string()))),
+		("time", format) => {
+			// an invalid format fails to be written, rather than panicking.
+			let mut time = String::new();
+			write!(time, "{}", Local::now().format(format.unwrap_or("%H:%M:%S"))).ok()?;
+			Some(Segment::Text(time))
+		}
+		("status", None) => Some(Segment::Text(if context.status == 0 { String::new() } else { context.status.to_string() })),
+		("duration", None) => Some(Segment::Text(context.duration.map_or(String::new(), format_duration))),
+		("rust", None) => Some(Segment::Text(if config.integrations.rust_integration { rust_segment() } else { String::new() })),
+		("git", None) => Some(Segment::Text(if config.integrations.git_integration { git_segment() } else { String::new() })),
+		("black", None) => style(Black.normal()),
+		("red", None) => style(Red.normal()),
+		("green", None) => style(Green.normal()),
+		("yellow", None) => style(Yellow.normal()),
+		("blue", None) => style(Blue.normal()),
+		("purple", None) => style(Purple.normal()),
+		("cyan", None) => style(Cyan.normal()),
+		("white", None) => style(White.normal()),
+		("rgb", Some(rgb)) => {
+			let channels = rgb.split(',').map(|f| f.trim().parse::<u8>().ok()).collect::<Option<Vec<u8>>>()?;
+			match channels[..] {
+				[r, g, b] => style(RGB(r, g, b).normal()),
+				_ => None
+			}
+		}
+		("bold", None) => style(Style::new().bold()),
+		("dimmed", None) => style(Style::new().dimmed()),
+		("italic", None) => style(Style::new().italic()),
+		("underline", None) => style(Style::new().underline()),
+		("reset", None) => Some(Segment::Style("\x1B[0m".to_string())),
+		_ => None
+	}
+}
+
+/// Formats a duration as milliseconds, seconds or minutes, whichever is the most readable.
+fn format_duration(duration: Duration) -> String {
+	let millis = duration.as_millis();
+	if millis < 1000 {
+		format!("{}ms", millis)
+	} else if millis < 60_000 {
+		format!("{:.1}s", duration.as_secs_f64())
+	} else {
+		format!("{}m {}s", millis / 60_000, (millis % 60_000) / 1000)
+	}
+}
+
+fn rust_segment() -> String {
+	let Ok(dir) = std::env::current_dir() else {
+		return String::new()
+	};
+	if !is_rust_project(&dir) {
+		String::new()
+	} else if is_cargo_workspace(&dir) {
+		"[cargo workspace]".to_string()
+	} else {
+		match get_crate_manifest(&dir).and_then(|f| f.package) {
+			Some(package) => format!("[crate \"{}\"]", package.name()),
+			None => "[rust project]".to_string()
+		}
+	}
+}
+
+fn git_segment() -> String {
+	std::env::current_dir().ok()
+		.and_then(get_nearest_git_repository)
+		.and_then(get_integration)
+		.unwrap_or_default()
+}
+
+#[test]
+fn test() {
+	let config = DeaconConfig::default();
+	let context = PromptContext { status: 3, duration: Some(Duration::from_millis(1500)) };
+	assert_eq!(render("{{{status}}} {duration} {unknown} $", &config, &context, true), "{3} 1.5s {unknown} $");
+	assert_eq!(render("{bold}{rgb:255,165,0}>{reset} ", &config, &context, false), "> ");
+	assert_eq!(render("{red}>", &config, &context, true), format!("{}>", Red.prefix()));
+	assert_eq!(render("{status}$", &config, &PromptContext::default(), true), "$");
+	assert_eq!(render("{time:%Q}", &config, &context, true), "{time:%Q}");
+}
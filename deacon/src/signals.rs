@@ -0,0 +1,238 @@
+//! Signal handling and the terminal's foreground process group.
+//!
+//! When the shell is interactive, every pipeline runs in its own process group, which is given the
+//! terminal while it runs. Ctrl-C and Ctrl-Z then send SIGINT and SIGTSTP to the pipeline, and not to the shell.
+
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use ansi_term::Colour::Red;
+
+/// The process group of the pipeline running in the foreground, or 0.
+static FOREGROUND: AtomicI32 = AtomicI32::new(0);
+/// A copy of the terminal's file descriptor if job control is enabled, or -1.
+static TERMINAL: AtomicI32 = AtomicI32::new(-1);
+/// Whether SIGINT was received since this was last checked.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+/// The end of the pipe written to when a child changes state or on Ctrl-C, while the shell waits for jobs, or -1.
+#[cfg(unix)]
+static WAKE: AtomicI32 = AtomicI32::new(-1);
+/// The terminal's modes when job control was enabled, restored when the shell takes the terminal back.
+#[cfg(unix)]
+static TERMINAL_MODES: std::sync::Mutex<Option<libc::termios>> = std::sync::Mutex::new(None);
+
+/// Keeps the shell alive on SIGINT, forwarding the signal to the foreground process group.
+pub fn install_handler() {
+	let result = ctrlc::set_handler(|| {
+		INTERRUPTED.store(true, Ordering::SeqCst);
+		// on Windows, every process attached to the console receives Ctrl-C by itself.
+		#[cfg(unix)]
+		{
+			let group = FOREGROUND.load(Ordering::SeqCst);
+			if group > 0 {
+				unsafe {
+					libc::kill(-group, libc::SIGINT);
+				}
+			}
+			wake();
+		}
+	});
+	if let Err(err) = result {
+		eprintln!("{}", Red.paint(format!("Failed to handle Ctrl-C: {}", err)));
+	}
+}
+
+/// Returns whether SIGINT was received since this was last called.
+pub fn take_interrupt() -> bool {
+	INTERRUPTED.swap(false, Ordering::SeqCst)
+}
+
+/// Records an interrupt if the exit code is the one of a process killed by SIGINT. While a pipeline has
+/// the terminal, Ctrl-C only reaches the pipeline, so this is how the shell learns about it.
+#[cfg(unix)]
+pub fn record_interrupt(code: i32) {
+	if code == 128 + libc::SIGINT {
+		INTERRUPTED.store(true, Ordering::SeqCst);
+	}
+}
+
+/// On other platforms, the shell receives Ctrl-C along with every process attached to the console.
+#[cfg(not(unix))]
+pub fn record_interrupt(_: i32) {}
+
+/// Returns whether job control is enabled, in which case every pipeline has its own process group.
+pub fn is_enabled() -> bool {
+	TERMINAL.load(Ordering::SeqCst) >= 0
+}
+
+/// Puts the shell in its own process group and takes the terminal, if the standard input is a terminal.
+/// The shell then ignores the signals which would stop it.
+#[cfg(unix)]
+pub fn enable_job_control() {
+	unsafe {
+		if libc::isatty(libc::STDIN_FILENO) == 0 {
+			return;
+		}
+		// wait until the shell is started in the foreground.
+		loop {
+			let group = libc::getpgrp();
+			if libc::tcgetpgrp(libc::STDIN_FILENO) == group {
+				break
+			}
+			libc::kill(-group, libc::SIGTTIN);
+		}
+		for signal in [libc::SIGTSTP, libc::SIGTTIN, libc::SIGTTOU] {
+			libc::signal(signal, libc::SIG_IGN);
+		}
+		let pid = libc::getpid();
+		// this fails if the shell already leads its session, and so its process group.
+		libc::setpgid(pid, pid);
+		libc::tcsetpgrp(libc::STDIN_FILENO, libc::getpgrp());
+		let terminal = libc::fcntl(libc::STDIN_FILENO, libc::F_DUPFD_CLOEXEC, 10);
+		let mut modes = std::mem::zeroed::<libc::termios>();
+		if libc::tcgetattr(terminal, &mut modes) == 0 {
+			*TERMINAL_MODES.lock().unwrap() = Some(modes);
+		}
+		TERMINAL.store(terminal, Ordering::SeqCst);
+	}
+}
+
+/// Job control is not supported on this platform.
+#[cfg(not(unix))]
+pub fn enable_job_control() {}
+
+/// Prepares a process of a pipeline to run. If job control is enabled, the process joins the given process group,
+/// or leads a new one, and takes the terminal before it starts if it runs in the foreground.
+#[cfg(unix)]
+pub fn prepare(command: &mut Command, group: Option<u32>, foreground: bool) {
+	use std::os::unix::process::CommandExt;
+	let terminal = TERMINAL.load(Ordering::SeqCst);
+	if terminal < 0 {
+		return;
+	}
+	command.process_group(group.map_or(0, |f| f as i32));
+	unsafe {
+		command.pre_exec(move || {
+			// take the terminal before the process can read from it, then stop ignoring what the shell ignores.
+			if foreground {
+				libc::tcsetpgrp(terminal, libc::getpgrp());
+			}
+			for signal in [libc::SIGTSTP, libc::SIGTTIN, libc::SIGTTOU] {
+				libc::signal(signal, libc::SIG_DFL);
+			}
+			Ok(())
+		});
+	}
+}
+
+#[cfg(not(unix))]
+pub fn prepare(_: &mut Command, _: Option<u32>, _: bool) {}
+
+/// Gives the terminal to a process group until dropped, when the shell takes the terminal back.
+pub struct Foreground(());
+
+impl Foreground {
+	pub fn new(group: u32) -> Foreground {
+		let terminal = TERMINAL.load(Ordering::SeqCst);
+		if terminal >= 0 {
+			FOREGROUND.store(group as i32, Ordering::SeqCst);
+			#[cfg(unix)]
+			unsafe {
+				libc::tcsetpgrp(terminal, group as i32);
+			}
+		}
+		Foreground(())
+	}
+}
+
+impl Drop for Foreground {
+	fn drop(&mut self) {
+		let terminal = TERMINAL.load(Ordering::SeqCst);
+		if terminal < 0 {
+			return;
+		}
+		FOREGROUND.store(0, Ordering::SeqCst);
+		#[cfg(unix)]
+		unsafe {
+			libc::tcsetpgrp(terminal, libc::getpgrp());
+			// a process which was stopped or killed may have left the terminal in another mode.
+			if let Some(modes) = *TERMINAL_MODES.lock().unwrap() {
+				libc::tcsetattr(terminal, libc::TCSADRAIN, &modes);
+			}
+		}
+	}
+}
+
+/// Wakes up the shell if it waits for jobs. This is safe to call from a signal handler.
+#[cfg(unix)]
+fn wake() {
+	let wake = WAKE.load(Ordering::SeqCst);
+	if wake >= 0 {
+		unsafe {
+			libc::write(wake, [0u8].as_ptr().cast(), 1);
+		}
+	}
+}
+
+/// Lets the shell block until a child changes state or Ctrl-C is pressed, until dropped.
+/// SIGCHLD and Ctrl-C write to a pipe, so one which happens before [Waiting::block] is not missed.
+#[cfg(unix)]
+pub struct Waiting {
+	pipe: [i32; 2],
+	/// The action for SIGCHLD before, restored when dropped.
+	previous: libc::sigaction
+}
+
+#[cfg(unix)]
+impl Waiting {
+	pub fn new() -> Waiting {
+		extern "C" fn on_child(_: libc::c_int) {
+			wake();
+		}
+		unsafe {
+			let mut pipe = [-1; 2];
+			if libc::pipe(pipe.as_mut_ptr()) == 0 {
+				for fd in pipe {
+					libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+					libc::fcntl(fd, libc::F_SETFL, libc::O_NONBLOCK);
+				}
+			}
+			let mut action = std::mem::zeroed::<libc::sigaction>();
+			action.sa_sigaction = on_child as extern "C" fn(libc::c_int) as libc::sighandler_t;
+			action.sa_flags = libc::SA_RESTART;
+			libc::sigemptyset(&mut action.sa_mask);
+			let mut previous = std::mem::zeroed::<libc::sigaction>();
+			libc::sigaction(libc::SIGCHLD, &action, &mut previous);
+			WAKE.store(pipe[1], Ordering::SeqCst);
+			Waiting { pipe, previous }
+		}
+	}
+
+	/// Blocks until a child changed state or Ctrl-C was pressed since this was last called.
+	pub fn block(&self) {
+		if self.pipe[0] < 0 {
+			// without a pipe, check again a bit later.
+			std::thread::sleep(std::time::Duration::from_millis(20));
+			return;
+		}
+		let mut fd = libc::pollfd { fd: self.pipe[0], events: libc::POLLIN, revents: 0 };
+		unsafe {
+			while libc::poll(&mut fd, 1, -1) < 0 && std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted {}
+			let mut buffer = [0u8; 64];
+			while libc::read(self.pipe[0], buffer.as_mut_ptr().cast(), buffer.len()) > 0 {}
+		}
+	}
+}
+
+#[cfg(unix)]
+impl Drop for Waiting {
+	fn drop(&mut self) {
+		WAKE.store(-1, Ordering::SeqCst);
+		unsafe {
+			libc::sigaction(libc::SIGCHLD, &self.previous, std::ptr::null_mut());
+			for fd in self.pipe.into_iter().filter(|f| *f >= 0) {
+				libc::close(fd);
+			}
+		}
+	}
+}
+
@@ -1,8 +1,12 @@
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use ansi_term::ANSIGenericString;
 use ansi_term::Colour::*;
 use comfy_table::*;
 use comfy_table::presets::UTF8_FULL;
 use lazy_static::lazy_static;
+use crate::commands::builtins;
 
 pub fn colorize_bool(boolean: bool) -> ANSIGenericString<'static, str> {
 	if boolean {
@@ -12,75 +16,75 @@ pub fn colorize_bool(boolean: bool) -> ANSIGenericString<'static, str> {
 	}
 }
 
-pub fn print_prompt() {
-	use crate::integrations::rust::*;
-	use crate::integrations::git;
-	use crate::integrations::git::get_nearest_git_repository;
-	print!(
-		"{}: {} ",
-		Green.bold().paint(format!("{}@{}", whoami::username(), whoami::hostname())),
-		Blue.bold().paint(std::env::current_dir().unwrap().to_string_lossy().trim_end())
-	);
-
-	// start of integrations
+lazy_static! {
+	static ref HELP_TABLE: Table = {
+		let mut table = Table::new();
+		table
+			.load_preset(UTF8_FULL)
+			.set_content_arrangement(ContentArrangement::Dynamic)
+			.set_header(vec!["Command", "Description", "Example"]);
+		for builtin in builtins() {
+			table.add_row(vec![builtin.usage(), builtin.help(), builtin.example()]);
+		}
+		table
+	};
+}
 
-	// rust integration
-	if is_rust_project(std::env::current_dir().unwrap()) {
-		print!("{} ", RGB(255, 165, 0).paint({
-			if is_cargo_workspace(std::env::current_dir().unwrap()) {
-				"[cargo workspace]".to_string()
-			} else {
-				let manifest = get_crate_manifest(std::env::current_dir().unwrap());
-				if let Some(manifest) = manifest {
-					if let Some(package) = manifest.package {
-						format!("[crate \"{}\"]", package.name())
-					} else {
-						"[rust project]".to_string()
-					}
-				} else {
-					"[rust project]".to_string()
-				}
-			}
-		}))
-	}
+pub fn print_help(stdout: &mut dyn Write) -> i32 {
+	let _ = writeln!(stdout, "{}", *HELP_TABLE);
+	0
+}
 
-	// git integration
-	if let Some(repo) = get_nearest_git_repository(std::env::current_dir().unwrap()) {
-		print!("{} ", RGB(255, 165, 0).paint(
-			git::get_integration(repo)
-				.unwrap_or("".to_string())
-		));
+/// Returns the path of the executable of the given name, either as a path or in the `PATH`.
+/// On Windows, the name may leave out the `.exe` extension.
+pub fn find_executable(name: &str) -> Option<PathBuf> {
+	if name.contains(['/', '\\']) {
+		return Some(PathBuf::from(name)).filter(|f| is_executable_file(f));
 	}
-
-	// end of integrations
-
-	println!();
+	let paths = std::env::var_os("PATH")?;
+	std::env::split_paths(&paths).find_map(|dir| {
+		let path = dir.join(name);
+		if is_executable_file(&path) {
+			Some(path)
+		} else if cfg!(windows) && is_executable_file(&dir.join(format!("{}.exe", name))) {
+			Some(dir.join(format!("{}.exe", name)))
+		} else {
+			None
+		}
+	})
 }
 
-lazy_static! {
-	static ref HELP_TABLE: Table = {
-		let mut table = Table::new();
-	table
-		.load_preset(UTF8_FULL)
-		.set_content_arrangement(ContentArrangement::Dynamic)
-		.set_header(vec!["Command", "Description", "Example"])
-		.add_row(vec![
-			"help",
-			"Print help information.",
-			"help"
-		]).add_row(vec![
-			"cd <path>",
-			"Change directory to the given path.",
-			"cd /"
-		]).add_row(vec![
-			"ls (path)",
-			"List the given directory's files. If a directory is not provided, it defaults to the current working directory.",
-			"ls"
-		]);
-		table
+/// Returns the names of the executables in the `PATH` which start with the prefix, sorted.
+/// On Windows, the names are without their `.exe` extension, as [find_executable] accepts them.
+pub fn executables(prefix: &str) -> BTreeSet<String> {
+	let mut names = BTreeSet::new();
+	let Some(paths) = std::env::var_os("PATH") else {
+		return names
 	};
+	for dir in std::env::split_paths(&paths) {
+		let Ok(entries) = dir.read_dir() else {
+			continue
+		};
+		for entry in entries.flatten() {
+			let name = entry.file_name().to_string_lossy().to_string();
+			let name = if cfg!(windows) { name.strip_suffix(".exe").unwrap_or(&name).to_string() } else { name };
+			if name.starts_with(prefix) && is_executable_file(&entry.path()) {
+				names.insert(name);
+			}
+		}
+	}
+	names
 }
 
-pub fn print_help() {
-	println!("{}", HELP_TABLE.to_string());
-}
\ No newline at end of file
+/// Returns whether the path is a file which can be run: on Unix, a file with an execute permission.
+fn is_executable_file(path: &Path) -> bool {
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::PermissionsExt;
+		path.metadata().is_ok_and(|f| f.is_file() && f.permissions().mode() & 0o111 != 0)
+	}
+	#[cfg(not(unix))]
+	{
+		path.is_file()
+	}
+}